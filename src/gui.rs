@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub use rusttype::Font;
 
@@ -7,16 +7,23 @@ use sdl2::pixels::Color;
 
 pub use sdl2::event::{Event, WindowEvent};
 pub use sdl2::rect::Rect;
-pub use sdl2::mouse::MouseButton;
+pub use sdl2::mouse::{MouseButton, SystemCursor};
 
-use crate::Point;
+use crate::{BoundingBox, Coordinate, Point, Projection};
+
+/// The bytes at the start of every image written by `SimpleImage::to_bytes`.
+const IMAGE_MAGIC: [u8; 4] = *b"APIM";
 
 /// Represents an bitmap image, with 32 bit per pixel.
+///
+/// Each pixel is stored as four bytes in the order blue, green, red, alpha (BGRA).
+/// Colors passed as `[u8; 4]`, like in `draw_polygon`, use the same order.
 #[derive(Clone)]
 pub struct SimpleImage {
     data: Vec<u8>,
     width: u32,
     height: u32,
+    clip: Option<Rect>,
 }
 
 impl SimpleImage {
@@ -27,7 +34,58 @@ impl SimpleImage {
             data: (0..len).map(|_| 0).collect(),
             width,
             height,
+            clip: None,
+        }
+    }
+
+    /// Create an image from a buffer of pixels in the standard RGBA byte order,
+    /// such as the output of most image decoders.
+    ///
+    /// Fails if the length of the buffer doesn't match the width and height.
+    pub fn from_rgba(width: u32, height: u32, mut data: Vec<u8>) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+
+        SimpleImage::from_bgra(width, height, data)
+    }
+
+    /// Create an image from a buffer of pixels in the native BGRA byte order.
+    ///
+    /// Fails if the length of the buffer doesn't match the width and height,
+    /// or if an image of that size couldn't be addressed at all.
+    pub fn from_bgra(width: u32, height: u32, data: Vec<u8>) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let len = (width as usize).checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or_else(|| format!("a {}x{} image is too large to hold in memory", width, height))?;
+        if data.len() != len {
+            return Err(format!(
+                "expected {} bytes for a {}x{} image, got {}",
+                len, width, height, data.len(),
+            ).into());
+        }
+
+        Ok(SimpleImage {
+            data,
+            width,
+            height,
+            clip: None,
+        })
+    }
+
+    /// Create an image from bytes written by `to_bytes`, for example when reading a cache of
+    /// rendered tiles back from disk.
+    ///
+    /// Fails if the bytes don't start with the header written by `to_bytes`,
+    /// or if the number of bytes after it doesn't match the width and height.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        if bytes.len() < 12 || bytes[0..4] != IMAGE_MAGIC {
+            return Err("not an image written by SimpleImage::to_bytes".into());
         }
+
+        let width = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let height = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        SimpleImage::from_bgra(width, height, bytes[12..].to_vec())
     }
 
     /// Create an image that is a rendering of the specified text in the specified font.
@@ -37,22 +95,193 @@ impl SimpleImage {
         size: f32,
         color: [u8; 3],
     ) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let layout = layout_text(font, text, size);
+        let mut buffer = SimpleImage::new(layout.width as u32, layout.height as u32);
+        buffer.draw_glyphs(&layout.glyphs, -layout.y_min, color);
+
+        Ok(buffer)
+    }
+
+    /// Create an image that is a rendering of the specified text in the specified font,
+    /// broken into several lines so that no line is wider than `max_width` pixels.
+    ///
+    /// See `wrap_text` for how the text is broken into lines.
+    pub fn create_wrapped_text_image(
+        font: &Font<'static>,
+        text: &str,
+        size: f32,
+        color: [u8; 3],
+        max_width: u32,
+    ) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let lines: Vec<TextLayout> = wrap_text(font, text, size, max_width).iter()
+            .map(|line| layout_text(font, line, size))
+            .collect();
+
+        let metrics = font.v_metrics(rusttype::Scale::uniform(size));
+        let line_height = metrics.ascent - metrics.descent + metrics.line_gap;
+        let width = lines.iter().map(|line| line.width).max().unwrap_or(0);
+        let height = (lines.len().max(1) - 1) as f32*line_height + metrics.ascent - metrics.descent;
+        let mut buffer = SimpleImage::new(width as u32, height.ceil() as u32);
+
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = metrics.ascent + i as f32*line_height;
+            buffer.draw_glyphs(&line.glyphs, baseline.round() as i32, color);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Create an image of text on a filled background, like the labels on many maps.
+    /// The background is the size of the text plus `padding` pixels on every side,
+    /// with its corners rounded off to `corner_radius` pixels. Use a radius of 0 for square corners.
+    ///
+    /// The text color is RGB, like for `create_text_image`,
+    /// while the background color is BGRA, like for the other drawing methods.
+    pub fn create_label_image(
+        font: &Font<'static>,
+        text: &str,
+        size: f32,
+        color: [u8; 3],
+        background: [u8; 4],
+        padding: u32,
+        corner_radius: u32,
+    ) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let text = SimpleImage::create_text_image(font, text, size, color)?;
+        let width = text.width() + 2*padding;
+        let height = text.height() + 2*padding;
+        let mut buffer = SimpleImage::new(width, height);
+
+        let radius = corner_radius.min(width/2).min(height/2) as f64;
+        for y in 0..height {
+            for x in 0..width {
+                // Distance into the rounded corner, if the pixel is in one.
+                let px = x as f64 + 0.5;
+                let py = y as f64 + 0.5;
+                let dx = (radius - px).max(px - (width as f64 - radius)).max(0.0);
+                let dy = (radius - py).max(py - (height as f64 - radius)).max(0.0);
+                if dx*dx + dy*dy <= radius*radius {
+                    buffer[(x, y)] = background;
+                }
+            }
+        }
+
+        text.blend_onto(&mut buffer, padding as i32, padding as i32, BlendMode::Blend);
+        Ok(buffer)
+    }
+
+    /// Draw text that follows a path of points onto this image, for example to label a river
+    /// or a road along its course. Each glyph is placed where its middle falls along the path,
+    /// measured from the first point, and rotated to the direction of the path there,
+    /// with the baseline of the text on the path. Glyphs past the end of the path are left out.
+    ///
+    /// Along a straight horizontal path, this draws the same as `create_text_image` does.
+    /// The text color is RGB, like for `create_text_image`.
+    pub fn draw_text_on_path(
+        &mut self,
+        font: &Font<'static>,
+        text: &str,
+        size: f32,
+        color: [u8; 3],
+        path: &[Point],
+    ) {
         let scale = rusttype::Scale::uniform(size);
-        let point = rusttype::point(0.0, 0.0);
-        let glyphs: Vec<_> = font.layout(text, scale, point).collect();
-        let (y_min, y_max, width) = glyphs.iter()
-            .filter_map(|glyph| glyph.pixel_bounding_box())
-            .fold((0i32, 0i32, 0i32), |(y_min, y_max, width), bbox| {
-                (
-                    y_min.min(bbox.min.y),
-                    y_max.max(bbox.max.y),
-                    width.max(bbox.max.x)
-                )
+        for glyph in font.layout(text, scale, rusttype::point(0.0, 0.0)) {
+            let bbox = match glyph.pixel_bounding_box() {
+                Some(x) => x,
+                None => continue,
+            };
+            let middle = glyph.position().x as f64 + glyph.unpositioned().h_metrics().advance_width as f64/2.0;
+            let (anchor, angle) = match point_along(path, middle) {
+                Some(x) => x,
+                None => break,
+            };
+
+            let mut image = SimpleImage::new(bbox.width() as u32, bbox.height() as u32);
+            glyph.draw(|x, y, w| {
+                image.set_pixel(x as i32, y as i32, [color[2], color[1], color[0], (255.0*w) as u8]);
             });
-        let height = y_max - y_min;
-        let mut buffer = SimpleImage::new(width as u32, height as u32);
 
-        for glyph in &glyphs {
+            // Turn the offset from the glyph's place on the baseline to the center of its image
+            // along with the glyph, to find where the center of the rotated image goes.
+            let offset_x = bbox.min.x as f64 + bbox.width() as f64/2.0 - middle;
+            let offset_y = bbox.min.y as f64 + bbox.height() as f64/2.0;
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let center_x = anchor.x + offset_x*cos - offset_y*sin;
+            let center_y = anchor.y + offset_x*sin + offset_y*cos;
+
+            let rotated = image.rotate(angle);
+            let x = (center_x - rotated.width() as f64/2.0).round() as i32;
+            let y = (center_y - rotated.height() as f64/2.0).round() as i32;
+            rotated.blend_onto(self, x, y, BlendMode::Blend);
+        }
+    }
+
+    /// Trace the outlines of the opaque parts of this image, for example to turn a rendered
+    /// region back into polygons for hit testing. Pixels with an alpha of at least
+    /// `alpha_threshold` count as inside.
+    ///
+    /// Uses marching squares, so outlines run along the edges of the pixels, except at corners,
+    /// which are cut off diagonally. Points along straight stretches are left out. Each outline
+    /// is a closed ring, ready for `draw_polygon` or `point_in_polygon`. Outlines around opaque
+    /// parts go clockwise on screen, and outlines around holes in them go counterclockwise.
+    pub fn trace_contours(&self, alpha_threshold: u8) -> Vec<Vec<Point>> {
+        let inside = |x: i32, y: i32| {
+            x >= 0 && y >= 0 && x < self.width as i32 && y < self.height as i32
+                && self[(x as u32, y as u32)][3] >= alpha_threshold
+        };
+
+        // Segments between the midpoints of the sides of each square of four pixel centers,
+        // in units of half pixels. The image is surrounded by outside pixels, so every outline closes.
+        let mut segments = std::collections::BTreeMap::new();
+        for y in -1..self.height as i32 {
+            for x in -1..self.width as i32 {
+                let corners = [inside(x, y), inside(x + 1, y), inside(x + 1, y + 1), inside(x, y + 1)];
+                let sides = [
+                    (2*x + 2, 2*y + 1),
+                    (2*x + 3, 2*y + 2),
+                    (2*x + 2, 2*y + 3),
+                    (2*x + 1, 2*y + 2),
+                ];
+
+                // Going clockwise around the square, connect every side leaving the inside
+                // to the next side entering it.
+                let leaving: Vec<usize> = (0..4).filter(|&i| corners[i] && !corners[(i + 1) % 4]).collect();
+                for i in leaving {
+                    let j = (1..4).map(|k| (i + k) % 4)
+                        .find(|&j| !corners[j] && corners[(j + 1) % 4])
+                        .unwrap_or(i);
+                    segments.insert(sides[i], sides[j]);
+                }
+            }
+        }
+
+        let mut contours = vec![];
+        while let Some(&start) = segments.keys().next() {
+            let mut ring = vec![];
+            let mut current = start;
+            while let Some(next) = segments.remove(&current) {
+                ring.push(current);
+                current = next;
+            }
+
+            // Leave out points in the middle of straight stretches.
+            let n = ring.len();
+            let contour: Vec<Point> = (0..n)
+                .filter(|&i| {
+                    let (a, b, c) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+                    (b.0 - a.0)*(c.1 - b.1) != (b.1 - a.1)*(c.0 - b.0)
+                })
+                .map(|i| Point::new(ring[i].0 as f64/2.0, ring[i].1 as f64/2.0))
+                .collect();
+            contours.push(contour);
+        }
+
+        contours
+    }
+
+    /// Draw laid out glyphs onto this image, with their baseline at the given y coordinate.
+    fn draw_glyphs(&mut self, glyphs: &[rusttype::PositionedGlyph<'static>], baseline: i32, color: [u8; 3]) {
+        for glyph in glyphs {
             let bbox = match glyph.pixel_bounding_box() {
                 Some(x) => x,
                 None => continue,
@@ -60,17 +289,15 @@ impl SimpleImage {
 
             glyph.draw(|x, y, w| {
                 let x = x as i32 + bbox.min.x;
-                let y = y as i32 + bbox.min.y - y_min;
-                buffer[(x as u32, y as u32)] = [
+                let y = y as i32 + bbox.min.y + baseline;
+                self.set_pixel(x, y, [
                     color[2],
                     color[1],
                     color[0],
                     (255.0*w) as u8,
-                ];
+                ]);
             });
         }
-
-        Ok(buffer)
     }
 
     /// Getter for the width of the image
@@ -78,72 +305,616 @@ impl SimpleImage {
     /// Getter for the height of the image
     pub fn height(&self) -> u32 { self.height }
 
+    /// Write the image into a compact block of bytes, which `from_bytes` reads back exactly,
+    /// for example to cache rendered tiles on disk without the cost of encoding them as PNG.
+    ///
+    /// The bytes are the four bytes `APIM`, the width and height as little endian `u32`s,
+    /// and then the pixels as they are, in the native BGRA byte order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + self.data.len());
+        bytes.extend_from_slice(&IMAGE_MAGIC);
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Copy the pixels of this image into a buffer in the standard RGBA byte order,
+    /// as expected by most other image libraries.
+    /// Dereferencing the image gives the pixels in the native BGRA order instead.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.data.chunks_exact(4)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+            .collect()
+    }
+
+    /// Set the color of a single pixel. Pixels outside of the image are ignored.
+    /// Pixels outside of the clip rectangle, if one is set, are ignored too.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if !self.is_writable(x, y) {
+            return;
+        }
+
+        self[(x as u32, y as u32)] = color;
+    }
+
+    /// Restrict drawing to a rectangle of the image, or remove the restriction with None.
+    ///
+    /// While a clip rectangle is set, all drawing methods, like `set_pixel`, `fill_rect`,
+    /// `draw_polygon` and drawing other images onto this one with `blend_onto`, leave the pixels
+    /// outside of it unchanged. Writing to pixels directly by indexing the image is not affected.
+    pub fn set_clip(&mut self, rect: Option<Rect>) {
+        self.clip = rect;
+    }
+
+    /// Getter for the current clip rectangle
+    pub fn clip(&self) -> Option<Rect> { self.clip }
+
+    /// Check whether a pixel lies within both the image and the clip rectangle.
+    fn is_writable(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return false;
+        }
+
+        match self.clip {
+            Some(clip) => clip.contains_point((x, y)),
+            None => true,
+        }
+    }
+
+    /// Set every pixel within a rectangle of the image to the provided color.
+    pub fn fill_rect(&mut self, rect: Rect, color: [u8; 4]) {
+        for y in rect.top()..rect.bottom() {
+            for x in rect.left()..rect.right() {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Draw a one pixel wide line between two points onto this image.
+    pub fn draw_line(&mut self, start: Point, end: Point, color: [u8; 4]) {
+        let start = (start.x as i32, start.y as i32);
+        let stop = (end.x as i32, end.y as i32);
+        for (x, y) in line_drawing::Bresenham::new(start, stop) {
+            self.set_pixel(x, y, color);
+        }
+    }
+
+    /// Draw lines between each consecutive pair of the provided points onto this image.
+    /// Unlike `draw_polygon`, the last point is not connected back to the first.
+    pub fn draw_polyline(&mut self, points: &[Point], color: [u8; 4]) {
+        for (p0, p1) in points.iter().zip(points.iter().skip(1)) {
+            self.draw_line(*p0, *p1, color);
+        }
+    }
+
+    /// Draw the great circle path between two coordinates onto this image, as `segments` straight
+    /// lines, on a map of the given width and height drawn with `Coordinate::screen`.
+    /// Where the path crosses the antimeridian, it leaves one side of the map and continues
+    /// from the other, instead of being drawn across the whole map.
+    pub fn draw_great_circle(
+        &mut self,
+        a: Coordinate,
+        b: Coordinate,
+        width: f64,
+        height: f64,
+        color: [u8; 4],
+        segments: usize,
+    ) {
+        let path = a.interpolate_n(b, segments.max(1) + 1);
+        for (c0, c1) in path.iter().zip(path.iter().skip(1)) {
+            let delta = c1.lon() - c0.lon();
+            if delta.abs() <= 180.0 {
+                self.draw_line(c0.screen(width, height), c1.screen(width, height), color);
+                continue;
+            }
+
+            // Find the latitude where the segment crosses the antimeridian, using continuous longitudes.
+            let edge = if delta < 0.0 { 180.0 } else { -180.0 };
+            let lon1 = c1.lon() + 2.0*edge;
+            let t = (edge - c0.lon())/(lon1 - c0.lon());
+            let lat = c0.lat() + t*(c1.lat() - c0.lat());
+
+            let exit = Coordinate::new(edge, lat).screen(width, height);
+            let entry = Coordinate::new(-edge, lat).screen(width, height);
+            self.draw_line(c0.screen(width, height), exit, color);
+            self.draw_line(entry, c1.screen(width, height), color);
+        }
+    }
+
+    /// Draw a dashed line between two points onto this image.
+    ///
+    /// Starting at `start`, the line alternates between `dash_len` pixels drawn
+    /// and `gap_len` pixels skipped, measured along the line.
+    pub fn draw_dashed_line(
+        &mut self,
+        start: Point,
+        end: Point,
+        color: [u8; 4],
+        dash_len: f64,
+        gap_len: f64,
+    ) {
+        let period = dash_len + gap_len;
+        if period <= 0.0 {
+            return;
+        }
+
+        let origin = (start.x as i32, start.y as i32);
+        let stop = (end.x as i32, end.y as i32);
+        for (x, y) in line_drawing::Bresenham::new(origin, stop) {
+            let travelled = Point::new(x as f64, y as f64)
+                .distance(Point::new(origin.0 as f64, origin.1 as f64));
+            if travelled % period < dash_len {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
     /// Given a list of all points that define it, draw a polygon onto this image.
     pub fn draw_polygon(&mut self, polygon: &[Point], color: [u8; 4])
     {
-        let lines: Vec<(Point, Point)> = polygon.iter()
-            .copied()
-            .zip(polygon.iter().copied().cycle().skip(1))
-            .collect();
+        PolygonRasterizer::new(polygon, color).step(self, usize::MAX);
+    }
 
-        let mut top = f64::MAX;
-        let mut bottom = 0.0f64;
-        for (p0, p1) in lines.iter() {
-            top = top.min(p0.y).min(p1.y);
-            bottom = bottom.max(p0.y).max(p1.y);
+    /// Fill a polygon onto this image, without drawing an outline around it.
+    pub fn fill_polygon(&mut self, polygon: &[Point], color: [u8; 4]) {
+        PolygonRasterizer::new(polygon, color).fill(self);
+    }
+
+    /// Fill a polygon onto this image with copies of a texture, repeated side by side from the
+    /// top left corner of the image, like `draw_tiled`. Useful for patterns such as hatching.
+    /// Like `fill_polygon`, no outline is drawn.
+    pub fn draw_polygon_textured(&mut self, polygon: &[Point], texture: &SimpleImage) {
+        if texture.width == 0 || texture.height == 0 {
+            return;
         }
 
-        let mut y = top;
-        while y < bottom {
-            let mut intersections = vec![];
-            for (mut a, mut b) in lines.iter().copied() {
-                if a.y == b.y {
-                    continue;
-                }
+        PolygonRasterizer::new(polygon, [0; 4]).fill_spans(|x0, x1, y| {
+            let ty = y.rem_euclid(texture.height as i32) as u32;
+            for x in x0..=x1 {
+                let tx = x.rem_euclid(texture.width as i32) as u32;
+                self.set_pixel(x, y, texture[(tx, ty)]);
+            }
+        });
+    }
 
-                if a.y > b.y {
-                    (a, b) = (b, a);
+    /// Fill a circle onto this image.
+    pub fn fill_circle(&mut self, center: Point, radius: f64, color: [u8; 4]) {
+        let r = radius.ceil() as i32;
+        let (cx, cy) = (center.x.round() as i32, center.y.round() as i32);
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if ((dx*dx + dy*dy) as f64) <= radius*radius {
+                    self.set_pixel(cx + dx, cy + dy, color);
                 }
-                if y < a.y || y > b.y {
+            }
+        }
+    }
+
+    /// Draw a line of the given width through all the points, in order.
+    ///
+    /// Each segment is filled as a polygon offset to both sides of the line,
+    /// and a circle is filled at every point to give the line round joins and caps.
+    pub fn draw_thick_polyline(&mut self, points: &[Point], width: f64, color: [u8; 4]) {
+        let half = width/2.0;
+        for (p0, p1) in points.iter().zip(points.iter().skip(1)) {
+            let length = p0.distance(*p1);
+            if length == 0.0 {
+                continue;
+            }
+
+            let nx = -(p1.y - p0.y)/length*half;
+            let ny = (p1.x - p0.x)/length*half;
+            self.fill_polygon(&[
+                Point::new(p0.x + nx, p0.y + ny),
+                Point::new(p1.x + nx, p1.y + ny),
+                Point::new(p1.x - nx, p1.y - ny),
+                Point::new(p0.x - nx, p0.y - ny),
+            ], color);
+        }
+
+        for point in points {
+            self.fill_circle(*point, half, color);
+        }
+    }
+
+    /// Draw a polygon onto this image like `draw_polygon`,
+    /// deciding which parts are inside with the given rule.
+    pub fn draw_polygon_with_rule(&mut self, polygon: &[Point], color: [u8; 4], rule: FillRule) {
+        PolygonRasterizer::with_fill_rule(polygon, color, rule).step(self, usize::MAX);
+    }
+
+    /// Draw several polygons onto this image, each with its own color, in order.
+    /// Gives the same result as calling `draw_polygon` for each of them,
+    /// but reuses the memory needed for drawing between the polygons.
+    pub fn draw_polygons(&mut self, polygons: &[(&[Point], [u8; 4])]) {
+        let mut rasterizer = PolygonRasterizer::new(&[], [0; 4]);
+        for (polygon, color) in polygons {
+            rasterizer.reset(polygon, *color);
+            rasterizer.step(self, usize::MAX);
+        }
+    }
+
+    /// Fill a rectangle of this image with copies of a smaller image, repeated side by side
+    /// starting from the top left corner of the rectangle. Copies along the right and bottom
+    /// edges are cut off where the rectangle ends. The pixels are copied as they are, without blending.
+    pub fn draw_tiled(&mut self, tile: &SimpleImage, region: Rect) {
+        if tile.width == 0 || tile.height == 0 {
+            return;
+        }
+
+        for y in region.top()..region.bottom() {
+            let ty = (y - region.top()) as u32 % tile.height;
+            for x in region.left()..region.right() {
+                let tx = (x - region.left()) as u32 % tile.width;
+                self.set_pixel(x, y, tile[(tx, ty)]);
+            }
+        }
+    }
+
+    /// Composite this image onto another image, with its top left corner at (x, y).
+    /// Parts of this image that fall outside of the destination image are skipped.
+    ///
+    /// Unlike `SimpleWindow::draw_image` this happens entirely in memory,
+    /// so many images can be combined before uploading the result to the screen once.
+    pub fn blend_onto(&self, dst: &mut SimpleImage, x: i32, y: i32, mode: BlendMode) {
+        self.blend_onto_gamma(dst, x, y, mode, false);
+    }
+
+    /// Composite this image onto another image like `blend_onto`, optionally in linear light.
+    ///
+    /// With `gamma_correct`, the sRGB colors are converted to linear intensities before they are
+    /// combined, and back afterwards. Semi-transparent overlays and the soft edges of text then
+    /// look as they should: 50% white over black gives 188 rather than 128, which is what SDL
+    /// and `blend_onto` give. Alpha itself is always combined linearly.
+    pub fn blend_onto_gamma(&self, dst: &mut SimpleImage, x: i32, y: i32, mode: BlendMode, gamma_correct: bool) {
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + self.width as i32).min(dst.width as i32);
+        let y1 = (y + self.height as i32).min(dst.height as i32);
+
+        for dy in y0..y1 {
+            for dx in x0..x1 {
+                if !dst.is_writable(dx, dy) {
                     continue;
                 }
 
-                if a.x == b.x {
-                    intersections.push(a.x as i32);
-                    continue;
+                let src = self[((dx - x) as u32, (dy - y) as u32)];
+                let pixel = &mut dst[(dx as u32, dy as u32)];
+                *pixel = mode.blend(src, *pixel, gamma_correct);
+            }
+        }
+    }
+
+    /// Create a copy of this image where the color of every pixel is replaced by the
+    /// provided color, keeping the alpha of each pixel. Useful for recoloring monochrome icons.
+    pub fn tint(&self, color: [u8; 3]) -> SimpleImage {
+        let mut image = self.clone();
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel[0] = color[2];
+            pixel[1] = color[1];
+            pixel[2] = color[0];
+        }
+        image
+    }
+
+    /// Create a grayscale copy of this image, where every color channel holds the luminance
+    /// of the pixel, computed as 0.299 red + 0.587 green + 0.114 blue. Alpha is kept.
+    pub fn grayscale(&self) -> SimpleImage {
+        let mut image = self.clone();
+        for pixel in image.data.chunks_exact_mut(4) {
+            let luminance = 0.299*pixel[2] as f64 + 0.587*pixel[1] as f64 + 0.114*pixel[0] as f64;
+            let gray = luminance.round().min(255.0) as u8;
+            pixel[0] = gray;
+            pixel[1] = gray;
+            pixel[2] = gray;
+        }
+        image
+    }
+
+    /// Create a copy of this image rotated clockwise by a number of quarter turns.
+    /// Every pixel is moved as it is, so unlike rotating by any angle, nothing is blurred,
+    /// and four quarter turns give back the original image.
+    pub fn rotate90(&self, turns: u32) -> SimpleImage {
+        let turns = turns % 4;
+        let (width, height) = if turns & 1 == 0 {
+            (self.width, self.height)
+        } else {
+            (self.height, self.width)
+        };
+
+        let mut image = SimpleImage::new(width, height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (nx, ny) = match turns {
+                    0 => (x, y),
+                    1 => (self.height - 1 - y, x),
+                    2 => (self.width - 1 - x, self.height - 1 - y),
+                    _ => (y, self.width - 1 - x),
+                };
+                image[(nx, ny)] = self[(x, y)];
+            }
+        }
+        image
+    }
+
+    /// Create a copy of this image rotated clockwise by any angle in degrees, around its center.
+    /// The new image is large enough to hold all of the rotated image, and the corners around
+    /// it are transparent. The pixels are interpolated with `sample_bilinear`, which blurs them
+    /// slightly, so use `rotate90` for quarter turns.
+    pub fn rotate(&self, angle: f64) -> SimpleImage {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let w = self.width as f64;
+        let h = self.height as f64;
+        let width = (w*cos.abs() + h*sin.abs() - 1e-9).ceil().max(0.0) as u32;
+        let height = (w*sin.abs() + h*cos.abs() - 1e-9).ceil().max(0.0) as u32;
+
+        let mut image = SimpleImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // Turn the center of the pixel back to where it was in this image.
+                let dx = x as f64 + 0.5 - width as f64/2.0;
+                let dy = y as f64 + 0.5 - height as f64/2.0;
+                let sx = dx*cos + dy*sin + w/2.0;
+                let sy = -dx*sin + dy*cos + h/2.0;
+                if sx >= 0.0 && sy >= 0.0 && sx <= w && sy <= h {
+                    image[(x, y)] = self.sample_bilinear(sx - 0.5, sy - 0.5);
                 }
+            }
+        }
+        image
+    }
 
-                let k = (b.y - a.y)/(b.x - a.x);
-                let m = a.y - k*a.x;
-                let x = (y as f64 - m)/k;
-                intersections.push(x as i32);
+    /// Count how many pixels have each value, from 0 to 255, in each channel.
+    /// The channels are in the same order as the pixels: blue, green, red, alpha.
+    pub fn histogram(&self) -> [[u32; 256]; 4] {
+        let mut histogram = [[0; 256]; 4];
+        for pixel in self.data.chunks_exact(4) {
+            for (channel, &value) in pixel.iter().enumerate() {
+                histogram[channel][value as usize] += 1;
             }
+        }
+        histogram
+    }
+
+    /// Fill the area around a pixel with a color, like the bucket tool of a paint program.
+    ///
+    /// The area is every pixel connected to (x, y), horizontally or vertically, through pixels
+    /// whose channels all differ by at most `tolerance` from the color of (x, y) itself.
+    /// Pixels outside of the clip rectangle, if one is set, are neither filled nor followed.
+    pub fn flood_fill(&mut self, x: u32, y: u32, new_color: [u8; 4], tolerance: u8) {
+        if !self.is_writable(x as i32, y as i32) {
+            return;
+        }
 
-            intersections.sort();
+        let seed = self[(x, y)];
+        let matches = |pixel: [u8; 4]| {
+            pixel.iter().zip(seed.iter()).all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+        };
 
-            for (x0, x1) in intersections.iter().copied()
-                .zip(intersections.iter().copied().skip(1))
-                .enumerate()
-                .filter_map(|(i, l)| if i % 2 == 0 { Some(l) } else { None }) {
+        let mut visited = vec![false; self.width as usize*self.height as usize];
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back((x as i32, y as i32));
+        visited[y as usize*self.width as usize + x as usize] = true;
+        while let Some((x, y)) = queue.pop_front() {
+            self[(x as u32, y as u32)] = new_color;
 
-                for x in x0..=x1 {
-                    self[(x as u32, y as u32)] = color;
+            for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if !self.is_writable(nx, ny) {
+                    continue;
+                }
+                let index = ny as usize*self.width as usize + nx as usize;
+                if !visited[index] && matches(self[(nx as u32, ny as u32)]) {
+                    visited[index] = true;
+                    queue.push_back((nx, ny));
                 }
             }
+        }
+    }
 
-            y += 0.5;
+    /// Make every pixel of the given RGB color fully transparent, like SDL's color keys,
+    /// for example for older images that use magenta `[0xFF, 0x00, 0xFF]` as their background.
+    /// The color channels are left alone, only the alpha of matching pixels is set to zero.
+    pub fn set_color_key(&mut self, color: [u8; 3]) {
+        let [r, g, b] = color;
+        for pixel in self.data.chunks_exact_mut(4) {
+            if pixel[..3] == [b, g, r] {
+                pixel[3] = 0;
+            }
         }
+    }
 
-        for (p0, p1) in lines.iter() {
-            let start = (p0.x as i32, p0.y as i32);
-            let stop = (p1.x as i32, p1.y as i32);
-            for (x, y) in line_drawing::Bresenham::new(start, stop) {
-                self[(x as u32, y as u32)] = [0, 0, 0, 0xFF].into();
+    /// Adjust the brightness and contrast of this image.
+    ///
+    /// Each color channel is transformed as `(value - 128)*contrast + 128 + 255*brightness`,
+    /// clamped to the range 0 to 255. Alpha is left alone.
+    /// A brightness of 0 and a contrast of 1 leaves the image unchanged.
+    pub fn adjust(&mut self, brightness: f64, contrast: f64) {
+        for pixel in self.data.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                let value = (*channel as f64 - 128.0)*contrast + 128.0 + 255.0*brightness;
+                *channel = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    /// Borrow a rectangular region of this image, to draw into it using coordinates local
+    /// to the region. The region is clipped to the bounds of this image.
+    pub fn sub_image_mut(&mut self, x: u32, y: u32, width: u32, height: u32) -> SubImageMut<'_> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+
+        SubImageMut {
+            image: self,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Compare this image with another image of the same size, pixel by pixel.
+    ///
+    /// Fails if the images are of different sizes.
+    pub fn diff(&self, other: &SimpleImage) -> Result<ImageDiff, Box<dyn std::error::Error>> {
+        if self.width != other.width || self.height != other.height {
+            return Err(format!(
+                "cannot compare a {}x{} image with a {}x{} image",
+                self.width, self.height, other.width, other.height,
+            ).into());
+        }
+
+        let mut diff = ImageDiff {
+            max_difference: 0,
+            differing_pixels: 0,
+        };
+        for (a, b) in self.data.chunks_exact(4).zip(other.data.chunks_exact(4)) {
+            let difference = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap_or(0);
+            diff.max_difference = diff.max_difference.max(difference);
+            if difference > 0 {
+                diff.differing_pixels += 1;
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Check whether this image is the same size as another image, and no channel of any pixel
+    /// differs by more than `tolerance` between them.
+    pub fn approx_equal(&self, other: &SimpleImage, tolerance: u8) -> bool {
+        match self.diff(other) {
+            Ok(diff) => diff.max_difference <= tolerance,
+            Err(_) => false,
+        }
+    }
+
+    /// Sample the image at a fractional pixel position, interpolating between the four nearest
+    /// pixels. Sampling exactly at whole pixel coordinates gives that pixel, and positions
+    /// outside of the image are clamped to its edges.
+    pub fn sample_bilinear(&self, x: f64, y: f64) -> [u8; 4] {
+        if self.width == 0 || self.height == 0 {
+            return [0; 4];
+        }
+
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let x0 = x.floor() as u32;
+        let y0 = y.floor() as u32;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = x - x0 as f64;
+        let fy = y - y0 as f64;
+
+        let (p00, p10, p01, p11) = (self[(x0, y0)], self[(x1, y0)], self[(x0, y1)], self[(x1, y1)]);
+        let mut sample = [0; 4];
+        for i in 0..4 {
+            let top = p00[i] as f64*(1.0 - fx) + p10[i] as f64*fx;
+            let bottom = p01[i] as f64*(1.0 - fx) + p11[i] as f64*fx;
+            sample[i] = (top*(1.0 - fy) + bottom*fy).round() as u8;
+        }
+        sample
+    }
+
+    /// Copy another image into a rectangle of this image, stretching it to fill the rectangle,
+    /// like `SimpleWindow::draw_image` does on screen. The pixels are interpolated with
+    /// `sample_bilinear` and copied as they are, without blending.
+    pub fn blit_scaled(&mut self, src: &SimpleImage, dst: Rect) {
+        let scale_x = src.width as f64/dst.width() as f64;
+        let scale_y = src.height as f64/dst.height() as f64;
+        for y in 0..dst.height() as i32 {
+            for x in 0..dst.width() as i32 {
+                // Sample at the position of the center of the pixel in the source.
+                let sx = (x as f64 + 0.5)*scale_x - 0.5;
+                let sy = (y as f64 + 0.5)*scale_y - 0.5;
+                self.set_pixel(dst.x() + x, dst.y() + y, src.sample_bilinear(sx, sy));
+            }
+        }
+    }
+
+    /// Create a copy of this map image, drawn in another projection.
+    ///
+    /// This image is assumed to cover the whole earth in the projection `from`. Every pixel of
+    /// the new image of the given size is found by converting it to a coordinate in the projection
+    /// `to`, and sampling this image where that coordinate lies. Pixels of the new image outside
+    /// of the area covered by `to` are left transparent.
+    pub fn reproject(&self, from: Projection, to: Projection, width: u32, height: u32) -> SimpleImage {
+        let mut image = SimpleImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let coordinate = Point::new(x as f64, y as f64)
+                    .coordinate_with(to, width as f64, height as f64);
+                if !(-180.0..=180.0).contains(&coordinate.lon())
+                    || !(-90.0..=90.0).contains(&coordinate.lat()) {
+                    continue;
+                }
+
+                let source = coordinate.screen_with(from, self.width as f64, self.height as f64);
+                image[(x, y)] = self.sample_bilinear(source.x, source.y);
             }
         }
+        image
+    }
+
+    /// Copy this image into an SDL surface with the same byte order.
+    fn to_surface(&self) -> Result<sdl2::surface::Surface<'static>, String> {
+        let mut surface = sdl2::surface::Surface::new(
+            self.width,
+            self.height,
+            sdl2::pixels::PixelFormatEnum::BGRA32,
+        )?;
+        let pitch = surface.pitch() as usize;
+        let row = 4*self.width as usize;
+        surface.with_lock_mut(|pixels| {
+            for (src, dst) in self.data.chunks_exact(row).zip(pixels.chunks_mut(pitch)) {
+                dst[..row].copy_from_slice(src);
+            }
+        });
+
+        Ok(surface)
+    }
+
+}
+
+/// The result of comparing two images with `SimpleImage::diff`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ImageDiff {
+    /// The largest difference of any channel of any pixel between the images
+    pub max_difference: u8,
+    /// The number of pixels that differ in any channel
+    pub differing_pixels: usize,
+}
+
+/// The error from drawing an image onto a window that is larger than the largest texture
+/// the renderer supports, when the image can't be split up into several textures,
+/// such as when it is rotated with `SimpleWindow::draw_image_ex`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TextureTooLarge {
+    /// The width of the image
+    pub width: u32,
+    /// The height of the image
+    pub height: u32,
+    /// The largest texture width the renderer supports
+    pub max_width: u32,
+    /// The largest texture height the renderer supports
+    pub max_height: u32,
+}
+
+impl std::fmt::Display for TextureTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "a {}x{} image is larger than the largest texture of {}x{}",
+            self.width, self.height, self.max_width, self.max_height,
+        )
     }
 }
 
+impl std::error::Error for TextureTooLarge {}
+
+/// Gives access to the raw pixel buffer, in the native BGRA byte order.
+/// Use `SimpleImage::to_rgba` to get the pixels in the standard RGBA order.
 impl std::ops::Deref for SimpleImage {
     type Target = [u8];
 
@@ -168,31 +939,816 @@ impl std::ops::IndexMut<(u32, u32)> for SimpleImage {
     }
 }
 
+/// A mutable view into a rectangular region of a `SimpleImage`,
+/// created with `SimpleImage::sub_image_mut`.
+///
+/// Coordinates are local to the region, so (0, 0) is its top left corner.
+/// Drawing outside of the region has no effect on the rest of the image.
+pub struct SubImageMut<'a> {
+    image: &'a mut SimpleImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl SubImageMut<'_> {
+    /// Getter for the width of the region
+    pub fn width(&self) -> u32 { self.width }
+    /// Getter for the height of the region
+    pub fn height(&self) -> u32 { self.height }
+
+    /// Set the color of a single pixel of the region. Pixels outside of the region are ignored.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+
+        self.image.set_pixel((self.x + x as u32) as i32, (self.y + y as u32) as i32, color);
+    }
+
+    /// Set every pixel of the region to the provided color.
+    pub fn fill(&mut self, color: [u8; 4]) {
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Draws a polygon onto an image a limited number of scanlines at a time.
+///
+/// Filling a very detailed polygon with `SimpleImage::draw_polygon` may take longer than a frame.
+/// A rasterizer lets the work be spread out over several frames instead, by calling `step`
+/// once per frame until it reports that the polygon is complete.
+/// The finished result is identical to that of `draw_polygon`.
+///
+/// Example
+/// ```
+/// let mut rasterizer = PolygonRasterizer::new(&coastline, [0x40, 0x80, 0x40, 0xFF]);
+/// window.run((), |window, _, events| {
+///     rasterizer.step(&mut image, 100);
+///     window.draw_image(&image, None, false)?;
+///     Ok(())
+/// });
+/// ```
+#[derive(Clone, Debug)]
+pub struct PolygonRasterizer {
+    lines: Vec<(Point, Point)>,
+    intersections: Vec<(i32, i32)>,
+    color: [u8; 4],
+    rule: FillRule,
+    y: f64,
+    bottom: f64,
+    complete: bool,
+}
+
+/// Call `span` once for each run of pixels covered by the spans of a row, then clear the row.
+fn merge_spans(row: &mut Vec<(i32, i32)>, y: i32, span: &mut impl FnMut(i32, i32, i32)) {
+    row.sort();
+    let mut spans = row.drain(..);
+    if let Some((mut x0, mut x1)) = spans.next() {
+        for (next0, next1) in spans {
+            if next0 > x1 + 1 {
+                span(x0, x1, y);
+                x0 = next0;
+            }
+            x1 = x1.max(next1);
+        }
+        span(x0, x1, y);
+    }
+}
+
+impl PolygonRasterizer {
+    /// Prepare to draw a polygon, given a list of all points that define it.
+    pub fn new(polygon: &[Point], color: [u8; 4]) -> Self {
+        PolygonRasterizer::with_fill_rule(polygon, color, FillRule::EvenOdd)
+    }
+
+    /// Prepare to draw a polygon like `new`, deciding which parts are inside with the given rule.
+    pub fn with_fill_rule(polygon: &[Point], color: [u8; 4], rule: FillRule) -> Self {
+        let mut rasterizer = PolygonRasterizer {
+            lines: vec![],
+            intersections: vec![],
+            color,
+            rule,
+            y: 0.0,
+            bottom: 0.0,
+            complete: false,
+        };
+        rasterizer.reset(polygon, color);
+        rasterizer
+    }
+
+    /// Start over with a new polygon, reusing the buffers allocated for the previous one.
+    fn reset(&mut self, polygon: &[Point], color: [u8; 4]) {
+        self.lines.clear();
+        self.lines.extend(polygon.iter()
+            .copied()
+            .zip(polygon.iter().copied().cycle().skip(1)));
+
+        let mut top = f64::MAX;
+        let mut bottom = 0.0f64;
+        for (p0, p1) in self.lines.iter() {
+            top = top.min(p0.y).min(p1.y);
+            bottom = bottom.max(p0.y).max(p1.y);
+        }
+
+        self.color = color;
+        self.y = top;
+        self.bottom = bottom;
+        self.complete = false;
+    }
+
+    /// Returns true once the whole polygon, including its outline, has been drawn.
+    pub fn is_complete(&self) -> bool { self.complete }
+
+    /// Fill at most `max_scanlines` more pixel rows of the polygon onto the image.
+    /// The outline is drawn by the call that fills the last row.
+    ///
+    /// Returns true once the polygon is complete.
+    pub fn step(&mut self, image: &mut SimpleImage, max_scanlines: usize) -> bool {
+        if self.complete {
+            return true;
+        }
+
+        let color = self.color;
+        let mut scanlines = 0;
+        while self.y < self.bottom && scanlines < max_scanlines {
+            // Every pixel row is sampled at two heights, half a pixel apart, like `draw_polygon` does.
+            for _ in 0..2 {
+                if self.y < self.bottom {
+                    self.fill_scanline(|x0, x1, y| {
+                        for x in x0..=x1 {
+                            image.set_pixel(x, y, color);
+                        }
+                    });
+                    self.y += 0.5;
+                }
+            }
+            scanlines += 1;
+        }
+
+        if self.y >= self.bottom {
+            self.draw_outline(image);
+            self.complete = true;
+        }
+
+        self.complete
+    }
+
+    /// Fill all remaining scanlines of the polygon, without drawing the outline.
+    fn fill(&mut self, image: &mut SimpleImage) {
+        let color = self.color;
+        self.fill_spans(|x0, x1, y| {
+            for x in x0..=x1 {
+                image.set_pixel(x, y, color);
+            }
+        });
+    }
+
+    /// Call `span` with the first x, last x and y of every horizontal span of pixels
+    /// within the polygon, for all remaining scanlines, without drawing the outline.
+    ///
+    /// Each pixel is covered by at most one span, since the spans of the two samples
+    /// of a pixel row are merged, so translucent colors are not blended twice.
+    fn fill_spans(&mut self, mut span: impl FnMut(i32, i32, i32)) {
+        let mut row = vec![];
+        let mut row_y = None;
+        while self.y < self.bottom {
+            self.fill_scanline(|x0, x1, y| {
+                if row_y != Some(y) {
+                    if let Some(row_y) = row_y {
+                        merge_spans(&mut row, row_y, &mut span);
+                    }
+                    row_y = Some(y);
+                }
+                row.push((x0, x1));
+            });
+            self.y += 0.5;
+        }
+        if let Some(row_y) = row_y {
+            merge_spans(&mut row, row_y, &mut span);
+        }
+        self.complete = true;
+    }
+
+    fn fill_scanline(&mut self, mut span: impl FnMut(i32, i32, i32)) {
+        let y = self.y;
+        let intersections = &mut self.intersections;
+        intersections.clear();
+        for (mut a, mut b) in self.lines.iter().copied() {
+            if a.y == b.y {
+                continue;
+            }
+
+            // Edges going down the screen count as 1 and edges going up as -1.
+            let direction = if a.y < b.y { 1 } else { -1 };
+            if a.y > b.y {
+                (a, b) = (b, a);
+            }
+            if y < a.y || y > b.y {
+                continue;
+            }
+            // Counting the ends of two edges meeting at a point twice would throw off the winding.
+            if self.rule == FillRule::NonZero && y == b.y {
+                continue;
+            }
+
+            if a.x == b.x {
+                intersections.push((a.x as i32, direction));
+                continue;
+            }
+
+            let k = (b.y - a.y)/(b.x - a.x);
+            let m = a.y - k*a.x;
+            let x = (y - m)/k;
+            intersections.push((x as i32, direction));
+        }
+
+        intersections.sort();
+
+        match self.rule {
+            FillRule::EvenOdd => {
+                for (x0, x1) in intersections.iter().map(|&(x, _)| x)
+                    .zip(intersections.iter().map(|&(x, _)| x).skip(1))
+                    .enumerate()
+                    .filter_map(|(i, l)| if i % 2 == 0 { Some(l) } else { None }) {
+
+                    span(x0, x1, y as i32);
+                }
+            },
+            FillRule::NonZero => {
+                let mut winding = 0;
+                let mut start = 0;
+                for &(x, direction) in intersections.iter() {
+                    if winding == 0 {
+                        start = x;
+                    }
+                    winding += direction;
+                    if winding == 0 {
+                        span(start, x, y as i32);
+                    }
+                }
+            },
+        }
+    }
+
+    fn draw_outline(&self, image: &mut SimpleImage) {
+        for (p0, p1) in self.lines.iter() {
+            let start = (p0.x as i32, p0.y as i32);
+            let stop = (p1.x as i32, p1.y as i32);
+            for (x, y) in line_drawing::Bresenham::new(start, stop) {
+                image.set_pixel(x, y, [0, 0, 0, 0xFF]);
+            }
+        }
+    }
+}
+
+/// Measure the width and height in pixels of the image `SimpleImage::create_text_image`
+/// would create for the same text, without rendering it.
+pub fn measure_text(font: &Font<'static>, text: &str, size: f32) -> (u32, u32) {
+    let layout = layout_text(font, text, size);
+    (layout.width as u32, layout.height as u32)
+}
+
+/// Break text into lines at word boundaries, so that each line is at most `max_width`
+/// pixels wide when rendered in the given font and size.
+///
+/// Words are only broken up when a single word is wider than `max_width` on its own.
+/// Line breaks already in the text are kept.
+pub fn wrap_text(font: &Font<'static>, text: &str, size: f32, max_width: u32) -> Vec<String> {
+    let fits = |line: &str| measure_text(font, line, size).0 <= max_width;
+
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if fits(&candidate) {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            for c in word.chars() {
+                line.push(c);
+                if !fits(&line) && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::take(&mut line));
+                    line.push(c);
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Decide which labels to show so that no two shown labels overlap.
+///
+/// Each candidate is the top left corner of a label along with its width and height, for
+/// example from `measure_text`. Candidates are considered in order, so put the most important
+/// labels first: a label is shown unless it overlaps a label that was shown before it.
+///
+/// Returns whether each candidate should be shown.
+pub fn place_labels(candidates: &[(Point, u32, u32)]) -> Vec<bool> {
+    let mut placed: Vec<(Point, u32, u32)> = vec![];
+    candidates.iter()
+        .map(|&(p, w, h)| {
+            let overlaps = placed.iter().any(|&(q, qw, qh)| {
+                p.x < q.x + qw as f64 && q.x < p.x + w as f64
+                    && p.y < q.y + qh as f64 && q.y < p.y + h as f64
+            });
+            if !overlaps {
+                placed.push((p, w, h));
+            }
+            !overlaps
+        })
+        .collect()
+}
+
+/// Rounds to the nearest pixel, with halfway coordinates rounded away from zero.
+impl From<Point> for sdl2::rect::Point {
+    fn from(point: Point) -> Self {
+        sdl2::rect::Point::new(point.x.round() as i32, point.y.round() as i32)
+    }
+}
+
+impl Point {
+    /// Create a rectangle of the given size centered on this point, rounded to whole pixels.
+    pub fn to_rect(&self, width: u32, height: u32) -> Rect {
+        let x = (self.x - width as f64/2.0).round() as i32;
+        let y = (self.y - height as f64/2.0).round() as i32;
+        Rect::new(x, y, width, height)
+    }
+}
+
+/// Check whether a point, such as the position of a mouse click, is within a circle.
+/// Matches the circles drawn by `SimpleWindow::stroke_circle` with the same center and radius,
+/// including their outer edge.
+pub fn circle_contains(cx: f64, cy: f64, radius: f64, point: Point) -> bool {
+    (point.x - cx).powi(2) + (point.y - cy).powi(2) <= radius.powi(2)
+}
+
+/// Check whether a point is within an ellipse with the radii `rx` and `ry` along its axes,
+/// turned `angle_deg` degrees clockwise. Matches the ellipses drawn by
+/// `SimpleWindow::fill_ellipse` with the same center, radii and angle, including their edge.
+pub fn ellipse_contains(cx: f64, cy: f64, rx: f64, ry: f64, angle_deg: f64, point: Point) -> bool {
+    // Turn the offset back by the angle, so that it is along the axes of the ellipse.
+    let (sin, cos) = angle_deg.to_radians().sin_cos();
+    let (dx, dy) = (point.x - cx, point.y - cy);
+    let u = dx*cos + dy*sin;
+    let v = -dx*sin + dy*cos;
+    (u/rx).powi(2) + (v/ry).powi(2) <= 1.0
+}
+
+/// Check whether a point, such as the position of a mouse click, is within a rectangle.
+/// Matches the pixels filled by `SimpleImage::fill_rect`: the left and top edges are included,
+/// the right and bottom edges are not.
+pub fn rect_contains(rect: Rect, point: Point) -> bool {
+    point.x >= rect.left() as f64 && point.x < rect.right() as f64
+        && point.y >= rect.top() as f64 && point.y < rect.bottom() as f64
+}
+
+/// Check whether any part of a box of screen coordinates, for example around a projected
+/// polygon from `BoundingBox::from_points`, is within the viewport. Skipping polygons whose
+/// boxes are not visible saves drawing them when only a small part of a large map is shown.
+pub fn is_bbox_visible(bbox: &BoundingBox, viewport: Rect) -> bool {
+    bbox.max_x >= viewport.left() as f64 && bbox.min_x < viewport.right() as f64
+        && bbox.max_y >= viewport.top() as f64 && bbox.min_y < viewport.bottom() as f64
+}
+
+/// Find the corners of the arrow drawn by `SimpleWindow::draw_north_arrow`: the tip,
+/// the right corner, the notch in the back, and the left corner.
+fn north_arrow(x: f64, y: f64, size: f64, rotation_deg: f64) -> [Point; 4] {
+    let (sin, cos) = rotation_deg.to_radians().sin_cos();
+    // Turn each corner of the upright arrow clockwise around the center.
+    let corner = |dx: f64, dy: f64| Point::new(x + dx*cos - dy*sin, y + dx*sin + dy*cos);
+    [
+        corner(0.0, -size/2.0),
+        corner(size/3.0, size/2.0),
+        corner(0.0, size/4.0),
+        corner(-size/3.0, size/2.0),
+    ]
+}
+
+/// Find the point at a distance along a path, and the direction of the path there
+/// in degrees clockwise from the x axis. None past either end of the path.
+fn point_along(path: &[Point], distance: f64) -> Option<(Point, f64)> {
+    if distance < 0.0 {
+        return None;
+    }
+
+    let mut travelled = 0.0;
+    for (p0, p1) in path.iter().zip(path.iter().skip(1)) {
+        let length = p0.distance(*p1);
+        if length > 0.0 && distance <= travelled + length {
+            let t = (distance - travelled)/length;
+            let point = Point::new(p0.x + t*(p1.x - p0.x), p0.y + t*(p1.y - p0.y));
+            return Some((point, (p1.y - p0.y).atan2(p1.x - p0.x).to_degrees()));
+        }
+        travelled += length;
+    }
+    None
+}
+
+struct TextLayout {
+    glyphs: Vec<rusttype::PositionedGlyph<'static>>,
+    y_min: i32,
+    width: i32,
+    height: i32,
+}
+
+fn layout_text(font: &Font<'static>, text: &str, size: f32) -> TextLayout {
+    let scale = rusttype::Scale::uniform(size);
+    let point = rusttype::point(0.0, 0.0);
+    let glyphs: Vec<_> = font.layout(text, scale, point).collect();
+    let (y_min, y_max, width) = glyphs.iter()
+        .filter_map(|glyph| glyph.pixel_bounding_box())
+        .fold((0i32, 0i32, 0i32), |(y_min, y_max, width), bbox| {
+            (
+                y_min.min(bbox.min.y),
+                y_max.max(bbox.max.y),
+                width.max(bbox.max.x)
+            )
+        });
+
+    TextLayout {
+        glyphs,
+        y_min,
+        width,
+        height: y_max - y_min,
+    }
+}
+
+/// Builds a map legend: a list of colors, each with a label saying what it means.
+///
+/// Example
+/// ```
+/// let legend = Legend::new()
+///     .add_entry([0x40, 0x80, 0x40, 0xFF], "Forest")
+///     .add_entry([0xC0, 0x80, 0x40, 0xFF], "Water");
+/// let image = legend.render(&font, 16.0)?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Legend {
+    entries: Vec<([u8; 4], String)>,
+}
+
+impl Legend {
+    /// Start a legend without any entries.
+    pub fn new() -> Self {
+        Legend {
+            entries: vec![],
+        }
+    }
+
+    /// Add a row to the legend, with a swatch of a BGRA color, like those given to `draw_polygon`.
+    pub fn add_entry(mut self, color: [u8; 4], label: &str) -> Self {
+        self.entries.push((color, label.to_string()));
+        self
+    }
+
+    /// Draw the legend as an image just large enough to hold it: a white box with
+    /// a row for each entry, in the order they were added. Each row is a square swatch
+    /// of the color, as tall as the text, followed by the label in black.
+    pub fn render(&self, font: &Font<'static>, size: f32) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let padding = (size/4.0).ceil() as u32;
+        let metrics = font.v_metrics(rusttype::Scale::uniform(size));
+        let row_height = (metrics.ascent - metrics.descent).ceil() as u32;
+
+        let labels = self.entries.iter()
+            .map(|(_, label)| SimpleImage::create_text_image(font, label, size, [0, 0, 0]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let label_width = labels.iter().map(|label| label.width()).max().unwrap_or(0);
+
+        let rows = self.entries.len() as u32;
+        let width = 3*padding + row_height + label_width;
+        let height = padding + rows*(row_height + padding);
+        let mut image = SimpleImage::new(width, height);
+        image.fill_rect(Rect::new(0, 0, width, height), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        for (i, ((color, _), label)) in self.entries.iter().zip(labels.iter()).enumerate() {
+            let top = (padding + i as u32*(row_height + padding)) as i32;
+            image.fill_rect(Rect::new(padding as i32, top, row_height, row_height), *color);
+
+            // Center the label vertically in the row.
+            let label_top = top + (row_height as i32 - label.height() as i32)/2;
+            label.blend_onto(&mut image, (2*padding + row_height) as i32, label_top, BlendMode::Blend);
+        }
+
+        Ok(image)
+    }
+}
+
+/// Decides which parts of a polygon are inside it, which matters for polygons whose edges cross.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FillRule {
+    /// A point is inside if a line from it to outside the polygon crosses an odd number of edges.
+    /// Where a polygon overlaps itself, like in the middle of a five pointed star, it is empty.
+    EvenOdd,
+    /// A point is inside if the polygon winds around it at all.
+    /// Where a polygon overlaps itself, like in the middle of a five pointed star, it is filled.
+    NonZero,
+}
+
+/// Decides how the pixels of a drawn image are combined with the pixels already there,
+/// both when drawing to the screen and when compositing images with `SimpleImage::blend_onto`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BlendMode {
+    /// No blending, all previous pixels are replaced by the drawn image.
+    None,
+    /// Regular alpha blending, also known as "over" compositing,
+    /// for images whose color channels are not multiplied by alpha.
+    Blend,
+    /// Alpha blending for images whose color channels have already been multiplied by alpha.
+    /// Using `Blend` for such images darkens their semi-transparent edges.
+    ///
+    /// On screen this needs a renderer that supports custom blend modes, which SDL's software
+    /// renderer does not. Where it is not supported, `Blend` is used instead,
+    /// which `SimpleWindow::blend_fallback_used` reports.
+    PremultipliedAlpha,
+    /// Adds the color of the image, scaled by its alpha, to the previous pixels.
+    /// Useful for glow effects, and for building up density where images overlap.
+    Add,
+    /// Multiplies the previous pixels with the color of the image, scaled by its alpha.
+    /// The result is never brighter than before, which is useful for shading.
+    Multiply,
+    /// The inverse of `Multiply`: the result is never darker than before.
+    /// On screen the alpha of the image is not taken into account for this mode.
+    ///
+    /// Like `PremultipliedAlpha`, this needs a renderer that supports custom blend modes on screen,
+    /// and falls back to `Blend` elsewhere.
+    Screen,
+}
+
+impl From<bool> for BlendMode {
+    /// `true` means `BlendMode::Blend`, and `false` means `BlendMode::None`.
+    fn from(blend: bool) -> Self {
+        if blend { BlendMode::Blend } else { BlendMode::None }
+    }
+}
+
+impl BlendMode {
+    /// Set the blend mode of a texture. Returns true if the renderer doesn't support the mode,
+    /// in which case regular alpha blending is set instead.
+    fn apply(self, texture: &mut sdl2::render::Texture) -> bool {
+        use sdl2::sys::SDL_BlendFactor::*;
+        use sdl2::sys::SDL_BlendOperation::*;
+
+        // SDL has no predefined modes for premultiplied alpha and screen, so compose them.
+        let (color_factors, alpha_factors) = match self {
+            BlendMode::None => {
+                texture.set_blend_mode(sdl2::render::BlendMode::None);
+                return false;
+            },
+            BlendMode::Blend => {
+                texture.set_blend_mode(sdl2::render::BlendMode::Blend);
+                return false;
+            },
+            BlendMode::Add => {
+                texture.set_blend_mode(sdl2::render::BlendMode::Add);
+                return false;
+            },
+            BlendMode::Multiply => {
+                texture.set_blend_mode(sdl2::render::BlendMode::Mul);
+                return false;
+            },
+            // dstRGBA = srcRGBA + dstRGBA * (1 - srcA)
+            BlendMode::PremultipliedAlpha => (
+                (SDL_BLENDFACTOR_ONE, SDL_BLENDFACTOR_ONE_MINUS_SRC_ALPHA),
+                (SDL_BLENDFACTOR_ONE, SDL_BLENDFACTOR_ONE_MINUS_SRC_ALPHA),
+            ),
+            // dstRGB = srcRGB * (1 - dstRGB) + dstRGB, dstA = dstA
+            BlendMode::Screen => (
+                (SDL_BLENDFACTOR_ONE_MINUS_DST_COLOR, SDL_BLENDFACTOR_ONE),
+                (SDL_BLENDFACTOR_ZERO, SDL_BLENDFACTOR_ONE),
+            ),
+        };
+
+        let result = unsafe {
+            let mode = sdl2::sys::SDL_ComposeCustomBlendMode(
+                color_factors.0,
+                color_factors.1,
+                SDL_BLENDOPERATION_ADD,
+                alpha_factors.0,
+                alpha_factors.1,
+                SDL_BLENDOPERATION_ADD,
+            );
+            sdl2::sys::SDL_SetTextureBlendMode(texture.raw(), mode)
+        };
+        // Renderers without custom blend modes, such as the software renderer, reject them.
+        if result != 0 {
+            texture.set_blend_mode(sdl2::render::BlendMode::Blend);
+            return true;
+        }
+
+        false
+    }
+
+    /// Combine a single source pixel with a destination pixel, in the same way SDL does,
+    /// or in linear light if `gamma_correct` is set.
+    fn blend(self, src: [u8; 4], dst: [u8; 4], gamma_correct: bool) -> [u8; 4] {
+        let to_linear = |v: f64| if !gamma_correct {
+            v
+        } else if v <= 0.04045 {
+            v/12.92
+        } else {
+            ((v + 0.055)/1.055).powf(2.4)
+        };
+        let to_srgb = |v: f64| if !gamma_correct {
+            v
+        } else if v <= 0.0031308 {
+            v*12.92
+        } else {
+            1.055*v.powf(1.0/2.4) - 0.055
+        };
+
+        let a = src[3] as f64/255.0;
+        let channel = |i: usize| {
+            let s = to_linear(src[i] as f64/255.0);
+            let d = to_linear(dst[i] as f64/255.0);
+            let v = match self {
+                BlendMode::None => s,
+                BlendMode::Blend => s*a + d*(1.0 - a),
+                BlendMode::PremultipliedAlpha => s + d*(1.0 - a),
+                BlendMode::Add => s*a + d,
+                BlendMode::Multiply => s*d + d*(1.0 - a),
+                BlendMode::Screen => s*a + d - s*a*d,
+            };
+            to_srgb(v.clamp(0.0, 1.0))
+        };
+        let da = dst[3] as f64/255.0;
+        let alpha = match self {
+            BlendMode::None => a,
+            BlendMode::Blend | BlendMode::PremultipliedAlpha => a + da*(1.0 - a),
+            BlendMode::Add | BlendMode::Multiply | BlendMode::Screen => da,
+        };
+
+        let to_byte = |v: f64| (255.0*v.clamp(0.0, 1.0)).round() as u8;
+        [
+            to_byte(channel(0)),
+            to_byte(channel(1)),
+            to_byte(channel(2)),
+            to_byte(alpha),
+        ]
+    }
+}
+
+/// Collects the parts of the screen that changed during a frame, merging overlapping rectangles,
+/// so that only those parts need to be cleared and drawn again.
+///
+/// A window keeps one of these for `SimpleWindow::mark_dirty`, but it can also be used on its own,
+/// for example to find the parts of a `SimpleImage` to redraw.
+#[derive(Clone, Debug, Default)]
+pub struct DirtyRegions {
+    rects: Vec<Rect>,
+}
+
+impl DirtyRegions {
+    /// Start without any dirty regions.
+    pub fn new() -> Self {
+        DirtyRegions {
+            rects: vec![],
+        }
+    }
+
+    /// Mark a rectangle as dirty. If it overlaps any of the regions marked before,
+    /// they are merged into the rectangle bounding them all.
+    pub fn mark(&mut self, rect: Rect) {
+        let mut merged = rect;
+        // A merged rectangle may overlap regions that neither part did, so repeat until none overlap.
+        while let Some(i) = self.rects.iter().position(|r| r.has_intersection(merged)) {
+            merged = merged.union(self.rects.swap_remove(i));
+        }
+        self.rects.push(merged);
+    }
+
+    /// The dirty regions, none of which overlap.
+    pub fn regions(&self) -> &[Rect] { &self.rects }
+
+    /// Check whether no region has been marked dirty.
+    pub fn is_empty(&self) -> bool { self.rects.is_empty() }
+
+    /// Forget all dirty regions, for example once they have been redrawn.
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+}
+
 /// Represents an SDL window
+///
+/// Only one window can exist at a time, since it owns the SDL context.
+/// Once a window has been closed, or dropped, a new one can be created.
 pub struct SimpleWindow {
-    context: sdl2::Sdl,
+    // Fields are dropped in order, so the cursor is freed before SDL shuts down.
+    cursor: Option<sdl2::mouse::Cursor>,
     canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    event_pump: sdl2::EventPump,
     width: u32,
     height: u32,
+    clear_color: [u8; 4],
+    auto_clear: bool,
+    dirty: DirtyRegions,
+    blend_fallback: bool,
+    frame_logger: Option<Box<dyn FnMut(FrameStats)>>,
+    frame_start: Instant,
+    draw_start: Instant,
+    frame_events: usize,
+    max_events_per_frame: Option<usize>,
+}
+
+/// Timing of a single frame, passed to the logger set with `SimpleWindow::set_frame_logger`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FrameStats {
+    /// Time from presenting the previous frame until presenting this one.
+    pub frame_time: Duration,
+    /// Time from polling the events of this frame until presenting it,
+    /// which is the time spent drawing, for example in the callback of `run`.
+    pub draw_time: Duration,
+    /// Number of events polled during this frame.
+    pub events: usize,
 }
 
 impl SimpleWindow {
     /// Creates a new window with the given pixel width and height.
     pub fn new(width: u32, height: u32) -> Result<SimpleWindow, Box<dyn std::error::Error>> {
+        SimpleWindow::create(width, height, None)
+    }
+
+    /// Creates a new window with the given pixel width and height on the display with the given
+    /// index, where 0 is the primary display. The top left corner of the window is placed at
+    /// `offset` from the top left corner of the display, or if it is None, the window is centered.
+    /// Use `display_count` and `display_bounds` before creating the window to find the displays.
+    ///
+    /// Example
+    /// ```
+    /// // Put the map in the top left corner of the last display.
+    /// let display = SimpleWindow::display_count()? - 1;
+    /// let window = SimpleWindow::new_on_display(800, 600, display, Some((0, 0)))?;
+    /// ```
+    pub fn new_on_display(
+        width: u32,
+        height: u32,
+        display: u32,
+        offset: Option<(i32, i32)>,
+    ) -> Result<SimpleWindow, Box<dyn std::error::Error>> {
+        SimpleWindow::create(width, height, Some((display, offset)))
+    }
+
+    fn create(
+        width: u32,
+        height: u32,
+        display: Option<(u32, Option<(i32, i32)>)>,
+    ) -> Result<SimpleWindow, Box<dyn std::error::Error>> {
         let context = sdl2::init()?;
         let video_subsystem = context.video()?;
 
-        let window = video_subsystem.window("rust-sdl2 demo", width, height)
-            .position_centered()
-            .build()?;
+        let mut builder = video_subsystem.window("rust-sdl2 demo", width, height);
+        match display {
+            Some((display, offset)) => {
+                let bounds = video_subsystem.display_bounds(display as i32)?;
+                let (x, y) = offset.unwrap_or((
+                    (bounds.width() as i32 - width as i32)/2,
+                    (bounds.height() as i32 - height as i32)/2,
+                ));
+                builder.position(bounds.x() + x, bounds.y() + y);
+            },
+            None => {
+                builder.position_centered();
+            },
+        }
+        let window = builder.build()?;
 
         let canvas = window.into_canvas().build()?;
+        let event_pump = context.event_pump()?;
+        video_subsystem.text_input().start();
 
         Ok(SimpleWindow {
-            context,
             canvas,
+            event_pump,
             width,
             height,
+            clear_color: [0, 0, 0, 0xFF],
+            auto_clear: true,
+            dirty: DirtyRegions::new(),
+            blend_fallback: false,
+            frame_logger: None,
+            frame_start: Instant::now(),
+            draw_start: Instant::now(),
+            frame_events: 0,
+            max_events_per_frame: None,
+            cursor: None,
         })
     }
 
@@ -201,75 +1757,750 @@ impl SimpleWindow {
     /// Getter for the height of the represented window.
     pub fn height(&self) -> u32 { self.height }
 
+    /// Get the number of displays connected, such as monitors, for example to choose one
+    /// for `new_on_display`.
+    ///
+    /// This starts SDL on its own, so like creating a window, it fails while a window exists.
+    pub fn display_count() -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(sdl2::init()?.video()?.num_video_displays()? as u32)
+    }
+
+    /// Get the area a display covers, in the coordinates used to position windows
+    /// across all displays. The primary display has index 0 and is at (0, 0).
+    ///
+    /// Like `display_count`, this fails while a window exists.
+    pub fn display_bounds(display: u32) -> Result<Rect, Box<dyn std::error::Error>> {
+        Ok(sdl2::init()?.video()?.display_bounds(display as i32)?)
+    }
+
+    /// Check whether the window has keyboard focus, meaning it is the window the user is using.
+    ///
+    /// The window also gets `Event::Window` events with `WindowEvent::FocusGained` and
+    /// `WindowEvent::FocusLost` when this changes. While the window is not focused, an
+    /// application can save power by skipping expensive redraws or drawing fewer frames.
+    ///
+    /// Example
+    /// ```
+    /// window.run(state, |window, state, _| {
+    ///     if window.has_focus() {
+    ///         state.animate();
+    ///     } else {
+    ///         // Keep showing the last frame, but check back less often.
+    ///         std::thread::sleep(Duration::from_millis(100));
+    ///     }
+    ///     window.draw_image(&state.frame, None, false)
+    /// });
+    /// ```
+    pub fn has_focus(&self) -> bool {
+        let focus = sdl2::sys::SDL_WindowFlags::SDL_WINDOW_INPUT_FOCUS as u32;
+        self.canvas.window().window_flags() & focus != 0
+    }
+
+    /// Get the SDL canvas of the window, for drawing with SDL directly or integrating other
+    /// libraries that need the underlying window.
+    pub fn canvas(&self) -> &sdl2::render::Canvas<sdl2::video::Window> {
+        &self.canvas
+    }
+
+    /// Get the SDL canvas of the window mutably, like `canvas`.
+    ///
+    /// Drawing to the canvas works alongside the methods of the window, but `run` still clears
+    /// and presents it every frame, so anything presented or cleared through the canvas directly
+    /// may be lost or shown at the wrong time. Changes to settings like the draw color or
+    /// the blend mode of the canvas may also be overwritten by the methods of the window.
+    pub fn canvas_mut(&mut self) -> &mut sdl2::render::Canvas<sdl2::video::Window> {
+        &mut self.canvas
+    }
+
+    /// Close the window and shut down SDL.
+    ///
+    /// This is what happens when the window is dropped, so it is only needed to make the point
+    /// where the window goes away explicit. Afterwards a new window may be created with `new`.
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// Set the icon shown for the window, for example in the taskbar.
+    /// Icons are usually square, and 32x32 or 64x64 pixels works well on most platforms.
+    pub fn set_icon(&mut self, image: &SimpleImage) -> Result<(), Box<dyn std::error::Error>> {
+        let surface = image.to_surface()?;
+        self.canvas.window_mut().set_icon(surface);
+        Ok(())
+    }
+
+    /// Use an image as the mouse cursor while it is over the window.
+    /// The hot spot at (hot_x, hot_y) in the image is the pixel that points at things.
+    ///
+    /// SDL only borrows the cursor, so the window keeps it alive until it is replaced
+    /// by another call to `set_cursor_from_image` or `set_system_cursor`.
+    pub fn set_cursor_from_image(&mut self, image: &SimpleImage, hot_x: i32, hot_y: i32) -> Result<(), Box<dyn std::error::Error>> {
+        let surface = image.to_surface()?;
+        let cursor = sdl2::mouse::Cursor::from_surface(surface, hot_x, hot_y)?;
+        self.set_cursor(cursor);
+        Ok(())
+    }
+
+    /// Use one of the cursors provided by the system, such as `SystemCursor::Hand`,
+    /// while the mouse is over the window. `SystemCursor::Arrow` is the usual cursor.
+    pub fn set_system_cursor(&mut self, cursor: SystemCursor) -> Result<(), Box<dyn std::error::Error>> {
+        let cursor = sdl2::mouse::Cursor::from_system(cursor)?;
+        self.set_cursor(cursor);
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, cursor: sdl2::mouse::Cursor) {
+        cursor.set();
+        self.cursor = Some(cursor);
+    }
+
+    /// Set the color the screen is cleared to before every frame of `run`,
+    /// including the very first clear before the loop starts. Defaults to opaque black.
+    pub fn set_clear_color(&mut self, color: [u8; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Decide whether `run` clears the screen before every frame, which it does by default.
+    /// The screen is still cleared once before the loop starts.
+    ///
+    /// Disabling this lets drawing accumulate across frames, but SDL does not promise that
+    /// the previous frame is kept after presenting it, so depending on the platform this may
+    /// flicker or show leftovers of older frames. A more reliable way to accumulate drawing
+    /// is to draw into a `SimpleImage` kept in the state, and draw that image every frame.
+    ///
+    /// With this disabled, `run` instead clears only the regions marked with `mark_dirty`.
+    pub fn set_auto_clear(&mut self, auto_clear: bool) {
+        self.auto_clear = auto_clear;
+    }
+
+    /// Check whether an image has been drawn with regular alpha blending instead of the
+    /// `BlendMode` it was drawn with, because the renderer doesn't support that mode.
+    /// This happens for `BlendMode::PremultipliedAlpha` and `BlendMode::Screen` with
+    /// SDL's software renderer, for example on machines without a graphics card.
+    pub fn blend_fallback_used(&self) -> bool { self.blend_fallback }
+
+    /// Mark a part of the screen as changed, for when `run` does not clear the screen every frame.
+    /// Before the next frame, the regions marked during this one are filled with the clear color
+    /// and forgotten, so only they need to be drawn again. Overlapping regions are merged.
+    ///
+    /// This saves work on maps that mostly stay the same, with a few small things moving over
+    /// them, but the same caveats as for `set_auto_clear` apply.
+    ///
+    /// Example
+    /// ```
+    /// window.set_auto_clear(false);
+    /// window.run(marker, |window, marker, _| {
+    ///     marker.x += 1.0;
+    ///     let target = marker.to_rect(16, 16);
+    ///     window.draw_image(&icon, Some(target), true)?;
+    ///     // Cleared before the next frame, which draws the marker in its new place.
+    ///     window.mark_dirty(target);
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty.mark(rect);
+    }
+
+    /// Clear the whole screen if `auto_clear` is set, and otherwise only the dirty regions.
+    fn clear_frame(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.auto_clear {
+            self.clear();
+        } else if !self.dirty.is_empty() {
+            let [r, g, b, a] = self.clear_color;
+            let previous_mode = self.canvas.blend_mode();
+            self.canvas.set_blend_mode(sdl2::render::BlendMode::None);
+            self.canvas.set_draw_color(Color::RGBA(r, g, b, a));
+            let result = self.canvas.fill_rects(self.dirty.regions());
+            self.canvas.set_blend_mode(previous_mode);
+            result?;
+        }
+        self.dirty.clear();
+        Ok(())
+    }
+
     /// Draw the provided image onto this screen.
     ///
     /// # Arguments
     ///
-    /// * `image`: image to draw.
-    /// * `target`: an optional rectangle that specifies the area of the screen the image should
-    /// be written to. If omitted, stretches the image to fit the entire screen.
-    /// * `blend`: if true, performs alpha blending. If false, all previous pixels within
-    /// the bounding box are discarded
-    pub fn draw_image(
+    /// * `image`: image to draw.
+    /// * `target`: an optional rectangle that specifies the area of the screen the image should
+    ///   be written to. If omitted, stretches the image to fit the entire screen.
+    /// * `blend`: a `BlendMode` deciding how the image is combined with the previous pixels.
+    ///   A bool may also be passed: true performs alpha blending, and false discards all
+    ///   previous pixels within the bounding box.
+    ///
+    /// Images larger than the largest texture the renderer supports are uploaded as several
+    /// smaller textures. The image methods that rotate or flip the image can't do that,
+    /// and fail with a `TextureTooLarge` error for such images instead.
+    pub fn draw_image(
+        &mut self,
+        image: &SimpleImage,
+        target: Option<sdl2::rect::Rect>,
+        blend: impl Into<BlendMode>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.copy_image(image, target, blend.into(), 0xFF, 0.0, None, (false, false))
+    }
+
+    /// Draw the provided image onto this screen like `draw_image`, rotated and flipped.
+    /// The rotation and flipping happen on the graphics card, which is much cheaper than
+    /// creating a rotated copy of the image, for example for markers that point somewhere.
+    ///
+    /// # Arguments
+    ///
+    /// * `image`: image to draw.
+    /// * `target`: an optional rectangle of the screen to draw the image to, like for `draw_image`.
+    /// * `angle_deg`: angle to rotate the image by, in degrees clockwise.
+    /// * `center`: point to rotate the image around, relative to the top left corner of `target`.
+    ///   If omitted, the image is rotated around the center of `target`.
+    /// * `flip_h`: whether to mirror the image horizontally.
+    /// * `flip_v`: whether to mirror the image vertically.
+    /// * `blend`: a `BlendMode` deciding how the image is combined with the previous pixels.
+    ///
+    /// Example
+    /// ```
+    /// // An arrow pointing up in the image, turned to point along the bearing.
+    /// let bearing = from.bearing(to);
+    /// let target = from.screen(width, height).to_rect(arrow.width(), arrow.height());
+    /// window.draw_image_ex(&arrow, Some(target), bearing, None, false, false, true)?;
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_image_ex(
+        &mut self,
+        image: &SimpleImage,
+        target: Option<sdl2::rect::Rect>,
+        angle_deg: f64,
+        center: Option<Point>,
+        flip_h: bool,
+        flip_v: bool,
+        blend: impl Into<BlendMode>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.copy_image(image, target, blend.into(), 0xFF, angle_deg, center, (flip_h, flip_v))
+    }
+
+    /// Draw the provided image onto this screen, with its alpha scaled by `opacity`,
+    /// from 0.0 for invisible to 1.0 for the image as it is. The image itself is not changed,
+    /// which makes this a cheap way to fade an image in or out over several frames.
+    ///
+    /// The arguments are otherwise the same as for `draw_image`. The opacity only affects how
+    /// the image is combined with the previous pixels, so it has no visible effect with
+    /// `BlendMode::None`, which is what passing false as `blend` gives.
+    ///
+    /// Example
+    /// ```
+    /// for frame in 0..60 {
+    ///     window.poll_events();
+    ///     window.clear();
+    ///     window.draw_image_with_opacity(&overlay, None, true, frame as f64/60.0)?;
+    ///     window.present();
+    /// }
+    /// ```
+    pub fn draw_image_with_opacity(
+        &mut self,
+        image: &SimpleImage,
+        target: Option<sdl2::rect::Rect>,
+        blend: impl Into<BlendMode>,
+        opacity: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let alpha = (opacity.clamp(0.0, 1.0)*255.0).round() as u8;
+        self.copy_image(image, target, blend.into(), alpha, 0.0, None, (false, false))
+    }
+
+    /// Upload an image to a texture and copy it to the screen, with all alpha scaled by `alpha_mod`/255.
+    /// Rotates it by `angle` degrees around `center` and flips it horizontally and vertically if set.
+    ///
+    /// Images larger than the renderer's largest texture are uploaded in several tiles, each drawn
+    /// to its part of the target, unless they are rotated or flipped, which gives `TextureTooLarge`.
+    #[allow(clippy::too_many_arguments)]
+    fn copy_image(
+        &mut self,
+        image: &SimpleImage,
+        target: Option<sdl2::rect::Rect>,
+        blend: BlendMode,
+        alpha_mod: u8,
+        angle: f64,
+        center: Option<Point>,
+        flip: (bool, bool),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Some renderers report 0 when they have no limit.
+        let info = self.canvas.info();
+        let limit = |max: u32| if max == 0 { u32::MAX } else { max };
+        let (max_width, max_height) = (limit(info.max_texture_width), limit(info.max_texture_height));
+
+        if image.width() <= max_width && image.height() <= max_height {
+            let region = (0, 0, image.width(), image.height());
+            return self.copy_region(image, region, target, blend, alpha_mod, angle, center, flip);
+        }
+        if angle != 0.0 || flip != (false, false) {
+            return Err(Box::new(TextureTooLarge {
+                width: image.width(),
+                height: image.height(),
+                max_width,
+                max_height,
+            }));
+        }
+
+        // Round the edges of every tile on screen the same way, so that neighboring tiles meet exactly.
+        let target = target.unwrap_or_else(|| Rect::new(0, 0, self.width, self.height));
+        let scale_x = target.width() as f64/image.width() as f64;
+        let scale_y = target.height() as f64/image.height() as f64;
+        let screen_x = |x: u32| target.x() + (x as f64*scale_x).round() as i32;
+        let screen_y = |y: u32| target.y() + (y as f64*scale_y).round() as i32;
+
+        for y in (0..image.height()).step_by(max_height as usize) {
+            let height = max_height.min(image.height() - y);
+            for x in (0..image.width()).step_by(max_width as usize) {
+                let width = max_width.min(image.width() - x);
+                let (x0, y0, x1, y1) = (screen_x(x), screen_y(y), screen_x(x + width), screen_y(y + height));
+                if x1 <= x0 || y1 <= y0 {
+                    continue;
+                }
+
+                let tile_target = Rect::new(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32);
+                self.copy_region(image, (x, y, width, height), Some(tile_target), blend, alpha_mod, 0.0, None, (false, false))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Upload the region (x, y, width, height) of an image to a texture and copy it to the screen,
+    /// as described for `copy_image`. The region must fit in a texture.
+    #[allow(clippy::too_many_arguments)]
+    fn copy_region(
+        &mut self,
+        image: &SimpleImage,
+        region: (u32, u32, u32, u32),
+        target: Option<sdl2::rect::Rect>,
+        blend: BlendMode,
+        alpha_mod: u8,
+        angle: f64,
+        center: Option<Point>,
+        flip: (bool, bool),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let texture_creator = self.canvas.texture_creator();
+        let (x, y, width, height) = region;
+
+        // Images are always stored in BGRA byte order, with buffers in other orders converted by
+        // constructors like `SimpleImage::from_rgba`. BGRA32 is defined by byte order, unlike
+        // the packed ARGB8888, which only matches it on little-endian machines.
+        let mut texture = texture_creator.create_texture_static(
+            sdl2::pixels::PixelFormatEnum::BGRA32,
+            width,
+            height,
+        )?;
+        if blend.apply(&mut texture) {
+            self.blend_fallback = true;
+        }
+        texture.set_alpha_mod(alpha_mod);
+        // The rows of the region are read from the image with the pitch of the whole image.
+        let offset = 4*(y as usize*image.width() as usize + x as usize);
+        texture.update(None, &image.data[offset..], 4*image.width() as usize)?;
+
+        self.canvas.copy_ex(
+            &texture,
+            None,
+            target,
+            angle,
+            center.map(sdl2::rect::Point::from),
+            flip.0,
+            flip.1,
+        )?;
+
+        Ok(())
+    }
+
+    /// Draw a circle directly onto the screen
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`: center x coordinate
+    /// * `cy`: center y coordinate
+    /// * `radius`: radius of the drawn circle
+    /// * `thickness`: thickness of the stroke of the circle
+    /// * `color`: color of the circle
+    ///
+    /// Strokes exactly one pixel thick are drawn with the midpoint circle algorithm, which only
+    /// visits the pixels on the circle, so many thin rings are quick to draw. It plots one pixel
+    /// per step around the circle, centered on the nearest pixel, so the ring is evenly thin
+    /// where the band of other thicknesses would be uneven.
+    pub fn stroke_circle(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        thickness: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if thickness == 1.0 {
+            return self.stroke_thin_circle(cx, cy, radius - 0.5, color);
+        }
+
+        let r0 = radius - thickness;
+        let r1 = radius;
+
+        self.draw_circle_region(cx, cy, radius, color, |dx, dy| {
+            let r = (dx.powi(2) + dy.powi(2)).sqrt();
+            r >= r0 && r <= r1
+        })
+    }
+
+    /// Draw rings at the given distances in meters around a coordinate, like on a radar display,
+    /// on a map of the given width and height drawn with `Coordinate::screen`.
+    ///
+    /// The size of each ring on screen is found by projecting the point that far east of the
+    /// center, so the rings are exact along the east-west line through the center, but only
+    /// approximate elsewhere, since the map stretches distances differently in other directions.
+    ///
+    /// If a font is given, each ring is labeled with its distance, such as "500 m" or "2.5 km",
+    /// centered on the top of the ring.
+    pub fn draw_range_rings(
+        &mut self,
+        center: Coordinate,
+        radii_m: &[f64],
+        width: f64,
+        height: f64,
+        color: [u8; 4],
+        font: Option<&Font<'static>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let origin = center.screen(width, height);
+        for &radius in radii_m {
+            let edge = center.destination(90.0, radius).screen(width, height);
+            // Wrap the edge around the map if the ring crosses the antimeridian.
+            let dx = (edge.x - origin.x).rem_euclid(width - 1.0);
+            let pixels = dx.min(width - 1.0 - dx).hypot(edge.y - origin.y);
+            self.stroke_circle(origin.x, origin.y, pixels, 1.0, color)?;
+
+            if let Some(font) = font {
+                let text = if radius < 1000.0 {
+                    format!("{} m", radius)
+                } else {
+                    format!("{} km", radius/1000.0)
+                };
+                let label = SimpleImage::create_text_image(font, &text, 12.0, [color[0], color[1], color[2]])?;
+                let target = Rect::new(
+                    (origin.x - label.width() as f64/2.0).round() as i32,
+                    (origin.y - pixels - label.height() as f64/2.0).round() as i32,
+                    label.width(),
+                    label.height(),
+                );
+                self.draw_image(&label, Some(target), true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw a circle directly onto the screen like `stroke_circle`, but with smooth edges.
+    ///
+    /// Pixels that the edges of the stroke pass through are drawn partly transparent,
+    /// by how much of each pixel the stroke covers, and blended with what is already there.
+    /// This looks much better for small circles, such as markers, but is slower.
+    pub fn stroke_circle_aa(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        thickness: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let r0 = radius - thickness;
+        let r1 = radius;
+
+        let x0 = ((cx - radius).floor() as i32 - 1).max(0);
+        let y0 = ((cy - radius).floor() as i32 - 1).max(0);
+        let x1 = ((cx + radius).ceil() as i32 + 1).min(self.width as i32);
+        let y1 = ((cy + radius).ceil() as i32 + 1).min(self.height as i32);
+
+        let previous_mode = self.canvas.blend_mode();
+        self.canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Approximate the part of the pixel within the stroke by the distance of its center to the edges.
+                let r = (x as f64 + 0.5 - cx).hypot(y as f64 + 0.5 - cy);
+                let coverage = (r1 - r + 0.5).clamp(0.0, 1.0)*(r - r0 + 0.5).clamp(0.0, 1.0);
+                if coverage <= 0.0 {
+                    continue;
+                }
+
+                let alpha = (color[3] as f64*coverage).round() as u8;
+                self.canvas.set_draw_color((color[0], color[1], color[2], alpha));
+                self.canvas.draw_point((x, y))?;
+            }
+        }
+
+        self.canvas.set_blend_mode(previous_mode);
+        Ok(())
+    }
+
+    /// Draw a filled ellipse directly onto the screen, for example to show the uncertainty
+    /// around a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`: center x coordinate
+    /// * `cy`: center y coordinate
+    /// * `rx`: radius along the first axis of the ellipse, horizontal before it is turned
+    /// * `ry`: radius along the second axis of the ellipse, vertical before it is turned
+    /// * `angle_deg`: angle to turn the ellipse by, in degrees clockwise
+    /// * `color`: color of the ellipse
+    pub fn fill_ellipse(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+        angle_deg: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_circle_region(cx, cy, rx.max(ry), color, |dx, dy| {
+            ellipse_contains(0.0, 0.0, rx, ry, angle_deg, Point::new(dx, dy))
+        })
+    }
+
+    /// Draw the stroke of an ellipse directly onto the screen. Like for `stroke_circle`,
+    /// the stroke lies inside of the radii, which are the same as for `fill_ellipse`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`: center x coordinate
+    /// * `cy`: center y coordinate
+    /// * `rx`: radius along the first axis of the ellipse, horizontal before it is turned
+    /// * `ry`: radius along the second axis of the ellipse, vertical before it is turned
+    /// * `angle_deg`: angle to turn the ellipse by, in degrees clockwise
+    /// * `thickness`: thickness of the stroke of the ellipse
+    /// * `color`: color of the ellipse
+    #[allow(clippy::too_many_arguments)]
+    pub fn stroke_ellipse(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        rx: f64,
+        ry: f64,
+        angle_deg: f64,
+        thickness: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (inner_rx, inner_ry) = (rx - thickness, ry - thickness);
+
+        self.draw_circle_region(cx, cy, rx.max(ry), color, |dx, dy| {
+            let point = Point::new(dx, dy);
+            ellipse_contains(0.0, 0.0, rx, ry, angle_deg, point)
+                && (inner_rx <= 0.0 || inner_ry <= 0.0
+                    || !ellipse_contains(0.0, 0.0, inner_rx, inner_ry, angle_deg, point))
+        })
+    }
+
+    /// Draw an arc, a part of the stroke of a circle, directly onto the screen.
+    ///
+    /// Angles are in degrees, where 0 points to the right and angles increase clockwise
+    /// on screen, since y increases downwards. The arc goes clockwise from `start_deg` to
+    /// `end_deg`, so an `end_deg` smaller than `start_deg` wraps around past 360.
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`: center x coordinate
+    /// * `cy`: center y coordinate
+    /// * `radius`: radius of the circle the arc is part of
+    /// * `start_deg`: angle the arc starts at
+    /// * `end_deg`: angle the arc ends at
+    /// * `thickness`: thickness of the stroke of the arc
+    /// * `color`: color of the arc
+    #[allow(clippy::too_many_arguments)]
+    pub fn stroke_arc(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        start_deg: f64,
+        end_deg: f64,
+        thickness: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let r0 = radius - thickness;
+        let r1 = radius;
+
+        self.draw_circle_region(cx, cy, radius, color, |dx, dy| {
+            let r = (dx.powi(2) + dy.powi(2)).sqrt();
+            r >= r0 && r <= r1 && angle_between(dy.atan2(dx).to_degrees(), start_deg, end_deg)
+        })
+    }
+
+    /// Draw a filled pie slice directly onto the screen.
+    ///
+    /// Angles follow the same conventions as in `stroke_arc`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cx`: center x coordinate
+    /// * `cy`: center y coordinate
+    /// * `radius`: radius of the circle the slice is part of
+    /// * `start_deg`: angle the slice starts at
+    /// * `end_deg`: angle the slice ends at
+    /// * `color`: color of the slice
+    pub fn fill_pie(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        start_deg: f64,
+        end_deg: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.draw_circle_region(cx, cy, radius, color, |dx, dy| {
+            let r = (dx.powi(2) + dy.powi(2)).sqrt();
+            r <= radius && angle_between(dy.atan2(dx).to_degrees(), start_deg, end_deg)
+        })
+    }
+
+    /// Draw a line between two points directly onto the screen.
+    pub fn draw_line(&mut self, start: Point, end: Point, color: [u8; 4]) -> Result<(), Box<dyn std::error::Error>> {
+        self.canvas.set_draw_color((color[0], color[1], color[2], color[3]));
+        self.canvas.draw_line(start, end)?;
+        Ok(())
+    }
+
+    /// Draw a grid of lines every `spacing` pixels over the whole window, to help with laying
+    /// out things on screen. If a font is given, every crossing of the grid is labeled with its
+    /// position in pixels.
+    ///
+    /// This is meant for debugging: creating the labels every frame is slow.
+    pub fn draw_debug_grid(
         &mut self,
-        image: &SimpleImage,
-        target: Option<sdl2::rect::Rect>,
-        blend: bool,
+        spacing: u32,
+        color: [u8; 4],
+        font: Option<&Font<'static>>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let texture_creator = self.canvas.texture_creator();
+        if spacing == 0 {
+            return Ok(());
+        }
 
-        let mut texture = texture_creator.create_texture_static(
-            sdl2::pixels::PixelFormatEnum::ARGB8888,
-            image.width(),
-            image.height(),
-        )?;
-        if blend {
-            texture.set_blend_mode(sdl2::render::BlendMode::Blend);
+        let (width, height) = (self.width as f64, self.height as f64);
+        for x in (0..self.width).step_by(spacing as usize) {
+            self.draw_line(Point::new(x as f64, 0.0), Point::new(x as f64, height), color)?;
+        }
+        for y in (0..self.height).step_by(spacing as usize) {
+            self.draw_line(Point::new(0.0, y as f64), Point::new(width, y as f64), color)?;
         }
-        texture.update(None, &*image, 4*image.width() as usize)?;
 
-        self.canvas.copy(
-            &texture,
-            None,
-            target,
-        )?;
+        if let Some(font) = font {
+            for y in (0..self.height).step_by(spacing as usize) {
+                for x in (0..self.width).step_by(spacing as usize) {
+                    let text = format!("({}, {})", x, y);
+                    let label = SimpleImage::create_text_image(font, &text, 12.0, [color[0], color[1], color[2]])?;
+                    let target = Rect::new(x as i32 + 2, y as i32 + 2, label.width(), label.height());
+                    self.draw_image(&label, Some(target), true)?;
+                }
+            }
+        }
 
         Ok(())
     }
 
-    /// Draw a circle directly onto the screen
+    /// Draw a north arrow centered at (x, y), pointing to north on a map that is turned
+    /// `rotation_deg` degrees clockwise, so at 0 it points straight up. The arrow is `size` pixels
+    /// long, and if a font is given, it is labeled with an upright "N" beyond its tip.
     ///
-    /// # Arguments
+    /// Example
+    /// ```
+    /// // In the top right corner of a map that follows the heading of a vehicle.
+    /// window.draw_north_arrow(760.0, 40.0, 48.0, -heading, [0x00, 0x00, 0x00, 0xFF], Some(&font))?;
+    /// ```
+    pub fn draw_north_arrow(
+        &mut self,
+        x: f64,
+        y: f64,
+        size: f64,
+        rotation_deg: f64,
+        color: [u8; 4],
+        font: Option<&Font<'static>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let arrow = north_arrow(x, y, size, rotation_deg);
+        self.fill_polygon(&arrow, color)?;
+        for (p0, p1) in arrow.iter().zip(arrow.iter().cycle().skip(1)) {
+            self.draw_line(*p0, *p1, color)?;
+        }
+
+        if let Some(font) = font {
+            let label = SimpleImage::create_text_image(font, "N", (size/3.0) as f32, [color[0], color[1], color[2]])?;
+            // Put the center of the label a bit past the tip, far enough to clear it in any direction.
+            let (sin, cos) = rotation_deg.to_radians().sin_cos();
+            let distance = size/2.0 + label.width().max(label.height()) as f64*0.75;
+            let cx = x + distance*sin;
+            let cy = y - distance*cos;
+            let target = Rect::new(
+                (cx - label.width() as f64/2.0).round() as i32,
+                (cy - label.height() as f64/2.0).round() as i32,
+                label.width(),
+                label.height(),
+            );
+            self.draw_image(&label, Some(target), true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fill a polygon directly onto the screen, without drawing an outline around it.
+    /// Uses the same scanline filling as `SimpleImage::fill_polygon`,
+    /// so shapes that change every frame don't need an image of the whole window.
     ///
-    /// * `cx`: center x coordinate
-    /// * `cy`: center y coordinate
-    /// * `radius`: radius of the drawn circle
-    /// * `thickness`: thickness of the stroke of the circle
-    /// * `color`: color of the circle
-    pub fn stroke_circle(
+    /// Example
+    /// ```
+    /// let triangle = [Point::new(100.0, 50.0), Point::new(150.0, 150.0), Point::new(50.0, 150.0)];
+    /// window.fill_polygon(&triangle, [0xFF, 0x00, 0x00, 0xFF])?;
+    /// ```
+    pub fn fill_polygon(&mut self, polygon: &[Point], color: [u8; 4]) -> Result<(), Box<dyn std::error::Error>> {
+        self.canvas.set_draw_color((color[0], color[1], color[2], color[3]));
+
+        let canvas = &mut self.canvas;
+        let mut result = Ok(());
+        PolygonRasterizer::new(polygon, color).fill_spans(|x0, x1, y| {
+            if result.is_ok() {
+                result = canvas.draw_line((x0, y), (x1, y));
+            }
+        });
+        Ok(result?)
+    }
+
+    /// Draw a one pixel wide circle with the midpoint circle algorithm, plotting each pixel once.
+    fn stroke_thin_circle(
+        &mut self,
+        cx: f64,
+        cy: f64,
+        radius: f64,
+        color: [u8; 4],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let points: Vec<sdl2::rect::Point> = midpoint_circle(cx.round() as i32, cy.round() as i32, radius.round() as i32)
+            .into_iter()
+            .map(|(x, y)| sdl2::rect::Point::new(x, y))
+            .collect();
+
+        self.canvas.set_draw_color((color[0], color[1], color[2], color[3]));
+        self.canvas.draw_points(points.as_slice())?;
+        Ok(())
+    }
+
+    /// Draw every pixel within `radius` of the center for which `inside` returns true,
+    /// given the offset of the pixel from the center.
+    fn draw_circle_region(
         &mut self,
         cx: f64,
         cy: f64,
         radius: f64,
-        thickness: f64,
         color: [u8; 4],
+        inside: impl Fn(f64, f64) -> bool,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let x0 = (cx - radius).max(0.0);
         let y0 = (cy - radius).max(0.0);
         let x1 = (cx + radius).min(self.width as f64);
         let y1 = (cy + radius).min(self.height as f64);
 
-        let r0 = radius - thickness;
-        let r1 = radius;
-
         self.canvas.set_draw_color((color[0], color[1], color[2], color[3]));
 
         let mut x = x0;
         while x < x1 {
             let mut y = y0;
             while y < y1 {
-                let r = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
-                if r < r0 || r > r1 {
+                if !inside(x - cx, y - cy) {
                     y += 1.0;
                     continue;
                 }
@@ -315,30 +2546,707 @@ impl SimpleWindow {
     pub fn run<F, S>(mut self, mut state: S, mut callback: F) -> Result<(), Box<dyn std::error::Error>>
         where F: FnMut(&mut SimpleWindow, &mut S, Vec<sdl2::event::Event>) -> Result<(), Box<dyn std::error::Error>>,
     {
-        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xFF));
-        self.canvas.clear();
+        self.clear();
         self.canvas.present();
-        let mut event_pump = self.context.event_pump()?;
-        'running: loop {
-            self.canvas.clear();
-
-            let mut events = vec![];
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit {..} |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                        break 'running
-                    },
-                    _ => events.push(event),
-                }
-            }
+        self.frame_start = Instant::now();
+        loop {
+            self.clear_frame()?;
+
+            let events = match self.poll_frame_events() {
+                Some(events) => events,
+                None => break,
+            };
 
             callback(&mut self, &mut state, events)?;
 
-            self.canvas.present();
+            self.present();
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+
+        Ok(())
+    }
+
+    /// Like `run`, but updates the state at a fixed rate, independent of how fast frames are drawn.
+    ///
+    /// Every frame, `update` is called as many times as needed to catch up with the time that
+    /// has passed, each time with `fixed_dt` as the time step, and then `render` is called once
+    /// with the events of the frame. Since every update covers the same amount of time, a
+    /// simulation behaves the same regardless of the frame rate.
+    ///
+    /// If updates fall more than a quarter of a second behind, for example when the window is
+    /// dragged, the missed time is skipped instead of being caught up with.
+    ///
+    /// Example
+    /// ```
+    /// window.run_fixed(
+    ///     Point::new(0.0, 100.0),
+    ///     Duration::from_millis(10),
+    ///     |agent, dt| {
+    ///         agent.x += 50.0*dt.as_secs_f64();
+    ///         Ok(())
+    ///     },
+    ///     |window, agent, _| {
+    ///         window.stroke_circle(agent.x, agent.y, 5.0, 2.0, [0xFF, 0xFF, 0xFF, 0xFF])
+    ///     },
+    /// );
+    /// ```
+    pub fn run_fixed<S, U, R>(
+        mut self,
+        mut state: S,
+        fixed_dt: Duration,
+        mut update: U,
+        mut render: R,
+    ) -> Result<(), Box<dyn std::error::Error>>
+        where U: FnMut(&mut S, Duration) -> Result<(), Box<dyn std::error::Error>>,
+              R: FnMut(&mut SimpleWindow, &mut S, Vec<sdl2::event::Event>) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        if fixed_dt.is_zero() {
+            return Err("the fixed time step must be larger than zero".into());
+        }
+
+        self.clear();
+        self.canvas.present();
+        self.frame_start = Instant::now();
+
+        let mut previous = Instant::now();
+        let mut accumulated = Duration::ZERO;
+        loop {
+            self.clear_frame()?;
+
+            let events = match self.poll_frame_events() {
+                Some(events) => events,
+                None => break,
+            };
+
+            let now = Instant::now();
+            let updates = fixed_updates(now - previous, fixed_dt, &mut accumulated);
+            previous = now;
+            for _ in 0..updates {
+                update(&mut state, fixed_dt)?;
+            }
+
+            render(&mut self, &mut state, events)?;
+
+            self.present();
             ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
         }
 
         Ok(())
     }
+
+    /// Poll the events of a frame of `run`, or None if the window should close.
+    fn poll_frame_events(&mut self) -> Option<Vec<Event>> {
+        let mut events = vec![];
+        for event in self.poll_events() {
+            match event {
+                Event::Quit {..} |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    return None
+                },
+                _ => events.push(event),
+            }
+        }
+        Some(events)
+    }
+
+    /// Get all events that have occurred since the last time events were polled,
+    /// or at most as many as set with `set_max_events_per_frame`.
+    ///
+    /// Together with `clear` and `present`, this allows driving the window from a loop of your
+    /// own instead of using `run`. Unlike `run`, quitting and pressing escape are not handled,
+    /// and are returned like any other event.
+    ///
+    /// Example
+    /// ```
+    /// 'running: loop {
+    ///     for event in window.poll_events() {
+    ///         if let Event::Quit {..} = event {
+    ///             break 'running;
+    ///         }
+    ///     }
+    ///     window.clear();
+    ///     window.stroke_circle(100.0, 100.0, 50.0, 2.0, [0xFF, 0xFF, 0xFF, 0xFF])?;
+    ///     window.present();
+    ///     std::thread::sleep(std::time::Duration::from_millis(16));
+    /// }
+    /// ```
+    ///
+    /// None of `poll_events`, `clear` and `present` sleep or wait for anything, so the same loop
+    /// works from an async task, pausing with the timer of the runtime instead, such as
+    /// `tokio::time::sleep(..).await`. SDL must only be used from the thread that created the
+    /// window, so the window can't be moved to other threads: run the task on a single threaded
+    /// runtime, or with `tokio::task::LocalSet`.
+    pub fn poll_events(&mut self) -> Vec<Event> {
+        let events: Vec<Event> = match self.max_events_per_frame {
+            Some(max) => (0..max).map_while(|_| self.event_pump.poll_event()).collect(),
+            None => self.event_pump.poll_iter().collect(),
+        };
+        self.frame_events += events.len();
+        self.draw_start = Instant::now();
+        events
+    }
+
+    /// Start receiving `Event::TextInput` events, which carry the text typed by the user
+    /// with the keyboard layout and any input method taken into account.
+    /// Text input is started when the window is created.
+    ///
+    /// Example
+    /// ```
+    /// let mut search = String::new();
+    /// window.run((), |window, _, events| {
+    ///     for event in events {
+    ///         if let Event::TextInput { text, .. } = event {
+    ///             search.push_str(&text);
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn start_text_input(&mut self) {
+        self.canvas.window().subsystem().text_input().start();
+    }
+
+    /// Stop receiving `Event::TextInput` events, for example when a search box loses focus.
+    /// Key presses still arrive as `Event::KeyDown`.
+    pub fn stop_text_input(&mut self) {
+        self.canvas.window().subsystem().text_input().stop();
+    }
+
+    /// Clear the whole screen to the color set with `set_clear_color`.
+    pub fn clear(&mut self) {
+        let [r, g, b, a] = self.clear_color;
+        self.canvas.set_draw_color(Color::RGBA(r, g, b, a));
+        self.canvas.clear();
+    }
+
+    /// Show everything drawn since the last time the screen was presented.
+    /// Returns right away, without waiting for the screen to refresh.
+    pub fn present(&mut self) {
+        let draw_end = Instant::now();
+        self.canvas.present();
+
+        let now = Instant::now();
+        if let Some(logger) = &mut self.frame_logger {
+            logger(FrameStats {
+                frame_time: now - self.frame_start,
+                draw_time: draw_end.saturating_duration_since(self.draw_start),
+                events: self.frame_events,
+            });
+        }
+        self.frame_start = now;
+        self.frame_events = 0;
+    }
+
+    /// Limit how many events are polled per frame, or remove the limit with None, which is the default.
+    ///
+    /// When events arrive faster than they can be handled, such as during fast mouse movement,
+    /// this keeps every frame short. Events beyond the limit stay queued in SDL, in order, and
+    /// are returned by the following frames, so nothing is dropped, though events may arrive a
+    /// few frames late. SDL drops new events if its queue grows to tens of thousands of events.
+    ///
+    /// A limit of 0 is treated as 1, since a window that polls no events could never be closed.
+    pub fn set_max_events_per_frame(&mut self, max: Option<usize>) {
+        self.max_events_per_frame = max.map(|max| max.max(1));
+    }
+
+    /// Set a function to call with the timing of every frame, or remove it with None.
+    /// It is called each time the screen is presented, which is once per frame of `run`.
+    ///
+    /// Example
+    /// ```
+    /// window.set_frame_logger(Some(Box::new(|stats: FrameStats| {
+    ///     if stats.frame_time > Duration::from_millis(20) {
+    ///         println!("slow frame: {:?}", stats);
+    ///     }
+    /// })));
+    /// ```
+    pub fn set_frame_logger(&mut self, logger: Option<Box<dyn FnMut(FrameStats)>>) {
+        self.frame_logger = logger;
+    }
+}
+
+/// Check whether an angle lies on the clockwise sweep from `start` to `end`, all in degrees.
+fn angle_between(angle: f64, start: f64, end: f64) -> bool {
+    if (end - start).abs() >= 360.0 {
+        return true;
+    }
+
+    let sweep = (end - start).rem_euclid(360.0);
+    (angle - start).rem_euclid(360.0) <= sweep
+}
+
+/// Find the pixels of a one pixel wide circle with the midpoint circle algorithm, each once,
+/// sorted by x and then y. A negative radius gives no pixels.
+fn midpoint_circle(cx: i32, cy: i32, radius: i32) -> Vec<(i32, i32)> {
+    // Walk one eighth of the circle, and mirror it into the other seven.
+    let mut points = vec![];
+    let mut x = radius;
+    let mut y = 0;
+    let mut error = 1 - radius;
+    while y <= x {
+        for (dx, dy) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+            points.push((cx + dx, cy + dy));
+        }
+
+        y += 1;
+        if error < 0 {
+            error += 2*y + 1;
+        } else {
+            x -= 1;
+            error += 2*(y - x) + 1;
+        }
+    }
+
+    // The mirrored eighths meet on the axes and the diagonals, so those pixels come twice.
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+/// Add the time elapsed since the last frame of `SimpleWindow::run_fixed` to the time that is
+/// yet to be simulated, and take as many steps of `fixed_dt` as fit out of it.
+/// Returns the number of updates to run. At most a quarter of a second is kept.
+fn fixed_updates(elapsed: Duration, fixed_dt: Duration, accumulated: &mut Duration) -> u32 {
+    *accumulated = (*accumulated + elapsed).min(Duration::from_millis(250));
+    let mut updates = 0;
+    while *accumulated >= fixed_dt {
+        *accumulated -= fixed_dt;
+        updates += 1;
+    }
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rasterizer_steps_match_draw_polygon() {
+        let polygon = [
+            Point::new(5.0, 3.0),
+            Point::new(50.0, 10.0),
+            Point::new(30.0, 25.0),
+            Point::new(58.0, 55.0),
+            Point::new(8.0, 45.0),
+        ];
+        let color = [0x40, 0x80, 0xC0, 0xFF];
+
+        let mut expected = SimpleImage::new(64, 64);
+        expected.draw_polygon(&polygon, color);
+
+        let mut image = SimpleImage::new(64, 64);
+        let mut rasterizer = PolygonRasterizer::new(&polygon, color);
+        // The polygon is 52 rows tall, so 10 rows at a time takes 6 steps.
+        let mut steps = 0;
+        while !rasterizer.step(&mut image, 10) {
+            steps += 1;
+        }
+        assert_eq!(steps, 5);
+        assert_eq!(&*image, &*expected);
+    }
+
+    #[test]
+    fn trace_contours_outlines_block() {
+        let mut image = SimpleImage::new(4, 4);
+        for (x, y) in [(1, 1), (2, 1), (1, 2), (2, 2)] {
+            image[(x, y)] = [0xFF; 4];
+        }
+
+        // One ring along the edges of the pixels, with the corners cut off diagonally.
+        let contours = image.trace_contours(0x80);
+        assert_eq!(contours.len(), 1);
+        let mut corners: Vec<(f64, f64)> = contours[0].iter().map(|p| (p.x, p.y)).collect();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(corners, [
+            (1.0, 1.5), (1.0, 2.5), (1.5, 1.0), (1.5, 3.0),
+            (2.5, 1.0), (2.5, 3.0), (3.0, 1.5), (3.0, 2.5),
+        ]);
+        assert!(crate::ring_is_clockwise(&contours[0]));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_border() {
+        // A white border around a single pixel close in color to the outside, and a pixel
+        // outside that differs from the rest by 8.
+        let mut image = SimpleImage::new(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                image[(x, y)] = [0x10, 0x10, 0x10, 0xFF];
+            }
+        }
+        for y in 2..5 {
+            for x in 2..5 {
+                image[(x, y)] = [0xFF, 0xFF, 0xFF, 0xFF];
+            }
+        }
+        image[(3, 3)] = [0x14, 0x10, 0x10, 0xFF];
+        image[(7, 7)] = [0x18, 0x10, 0x10, 0xFF];
+
+        let red = [0x00, 0x00, 0xFF, 0xFF];
+        let mut exact = image.clone();
+        exact.flood_fill(0, 0, red, 0);
+        assert_eq!(exact[(6, 6)], red);
+        assert_eq!(exact[(7, 7)], [0x18, 0x10, 0x10, 0xFF]);
+        assert_eq!(exact[(2, 2)], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(exact[(3, 3)], [0x14, 0x10, 0x10, 0xFF]);
+
+        // Within the tolerance, but still not through the border.
+        let mut tolerant = image.clone();
+        tolerant.flood_fill(0, 0, red, 8);
+        assert_eq!(tolerant[(7, 7)], red);
+        assert_eq!(tolerant[(2, 2)], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(tolerant[(3, 3)], [0x14, 0x10, 0x10, 0xFF]);
+    }
+
+    #[test]
+    fn dirty_regions_merge_overlapping_rects() {
+        let mut dirty = DirtyRegions::new();
+        dirty.mark(Rect::new(0, 0, 10, 10));
+        dirty.mark(Rect::new(5, 5, 10, 10));
+        assert_eq!(dirty.regions(), [Rect::new(0, 0, 15, 15)]);
+
+        dirty.mark(Rect::new(40, 40, 5, 5));
+        assert_eq!(dirty.regions().len(), 2);
+
+        // Growing the first region to overlap the second merges all three.
+        dirty.mark(Rect::new(12, 12, 30, 30));
+        assert_eq!(dirty.regions(), [Rect::new(0, 0, 45, 45)]);
+
+        dirty.clear();
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn fill_rules_differ_inside_star() {
+        // A five pointed star drawn as one self-intersecting ring, visiting every second point.
+        let star: Vec<Point> = (0..5)
+            .map(|i| {
+                let angle = (-90.0 + 144.0*i as f64).to_radians();
+                Point::new(32.0 + 24.0*angle.cos(), 32.0 + 24.0*angle.sin())
+            })
+            .collect();
+        let color = [0xFF, 0x00, 0x00, 0xFF];
+
+        let mut even_odd = SimpleImage::new(64, 64);
+        even_odd.draw_polygon_with_rule(&star, color, FillRule::EvenOdd);
+        let mut non_zero = SimpleImage::new(64, 64);
+        non_zero.draw_polygon_with_rule(&star, color, FillRule::NonZero);
+
+        // The pentagon in the middle is enclosed twice, and the point of the star above it once.
+        assert_eq!(even_odd[(32, 32)], [0; 4]);
+        assert_eq!(non_zero[(32, 32)], color);
+        assert_eq!(even_odd[(32, 14)], color);
+        assert_eq!(non_zero[(32, 14)], color);
+    }
+
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut image = SimpleImage::new(3, 2);
+        image[(0, 0)] = [1, 2, 3, 4];
+        image[(2, 1)] = [0xFF, 0x80, 0x00, 0x40];
+
+        let copy = SimpleImage::from_bytes(&image.to_bytes()).unwrap();
+        assert_eq!((copy.width(), copy.height()), (3, 2));
+        assert_eq!(&*copy, &*image);
+    }
+
+    #[test]
+    fn from_bytes_rejects_bad_headers() {
+        let bytes = SimpleImage::new(3, 2).to_bytes();
+        assert!(SimpleImage::from_bytes(&bytes[..8]).is_err());
+        assert!(SimpleImage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(SimpleImage::from_bytes(b"PNG\0\x03\0\0\0\x02\0\0\0").is_err());
+
+        // A size too large to address must fail, rather than overflow.
+        let mut huge = IMAGE_MAGIC.to_vec();
+        huge.extend_from_slice(&(1u32 << 31).to_le_bytes());
+        huge.extend_from_slice(&(1u32 << 31).to_le_bytes());
+        assert!(SimpleImage::from_bytes(&huge).is_err());
+        huge[4..12].copy_from_slice(&[0xFF; 8]);
+        assert!(SimpleImage::from_bytes(&huge).is_err());
+    }
+
+    #[test]
+    fn fill_spans_cover_each_pixel_once() {
+        let polygon = [
+            Point::new(5.0, 3.0),
+            Point::new(50.0, 10.0),
+            Point::new(30.0, 25.0),
+            Point::new(58.0, 55.0),
+            Point::new(8.0, 45.0),
+        ];
+        let mut expected = SimpleImage::new(64, 64);
+        expected.fill_polygon(&polygon, [0xFF; 4]);
+
+        let mut covered = vec![0; 64*64];
+        PolygonRasterizer::new(&polygon, [0xFF; 4]).fill_spans(|x0, x1, y| {
+            for x in x0..=x1 {
+                covered[(y*64 + x) as usize] += 1;
+            }
+        });
+        assert!(covered.iter().all(|&count| count <= 1));
+        for (i, &count) in covered.iter().enumerate() {
+            let filled = expected[((i % 64) as u32, (i / 64) as u32)] == [0xFF; 4];
+            assert_eq!(count == 1, filled);
+        }
+    }
+
+    #[test]
+    fn fixed_updates_carry_remainder_and_clamp() {
+        let dt = Duration::from_millis(10);
+        let mut accumulated = Duration::ZERO;
+
+        assert_eq!(fixed_updates(Duration::from_millis(25), dt, &mut accumulated), 2);
+        assert_eq!(accumulated, Duration::from_millis(5));
+        assert_eq!(fixed_updates(Duration::from_millis(5), dt, &mut accumulated), 1);
+        assert_eq!(accumulated, Duration::ZERO);
+        assert_eq!(fixed_updates(Duration::from_millis(3), dt, &mut accumulated), 0);
+        assert_eq!(accumulated, Duration::from_millis(3));
+
+        // A long stall only catches up with a quarter of a second.
+        assert_eq!(fixed_updates(Duration::from_secs(2), dt, &mut accumulated), 25);
+        assert_eq!(accumulated, Duration::ZERO);
+    }
+
+    #[test]
+    fn blend_onto_add_multiply_and_screen() {
+        let square = |color: [u8; 4]| SimpleImage::from_bgra(4, 4, color.repeat(16)).unwrap();
+        let half_red = square([0x00, 0x00, 0x80, 0xFF]);
+
+        // The overlap of two half-intensity squares added together is twice as bright.
+        let mut image = square([0x00, 0x00, 0x00, 0xFF]);
+        half_red.blend_onto(&mut image, -2, 0, BlendMode::Add);
+        half_red.blend_onto(&mut image, 1, 0, BlendMode::Add);
+        assert_eq!(image[(0, 0)], [0x00, 0x00, 0x80, 0xFF]);
+        assert_eq!(image[(1, 0)], [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(image[(2, 0)], [0x00, 0x00, 0x80, 0xFF]);
+
+        let mut image = square([200, 200, 200, 0xFF]);
+        square([0x80, 0x80, 0x80, 0xFF]).blend_onto(&mut image, 0, 0, BlendMode::Multiply);
+        assert_eq!(image[(0, 0)], [100, 100, 100, 0xFF]);
+
+        let mut image = square([0x80, 0x80, 0x80, 0xFF]);
+        square([0x80, 0x80, 0x80, 0xFF]).blend_onto(&mut image, 0, 0, BlendMode::Screen);
+        assert_eq!(image[(0, 0)], [192, 192, 192, 0xFF]);
+    }
+
+    #[test]
+    fn gamma_correct_blending_of_half_white_over_black() {
+        let white = SimpleImage::from_bgra(1, 1, vec![0xFF, 0xFF, 0xFF, 0x80]).unwrap();
+
+        let mut image = SimpleImage::from_bgra(1, 1, vec![0x00, 0x00, 0x00, 0xFF]).unwrap();
+        white.blend_onto_gamma(&mut image, 0, 0, BlendMode::Blend, true);
+        assert_eq!(image[(0, 0)], [188, 188, 188, 0xFF]);
+
+        let mut image = SimpleImage::from_bgra(1, 1, vec![0x00, 0x00, 0x00, 0xFF]).unwrap();
+        white.blend_onto_gamma(&mut image, 0, 0, BlendMode::Blend, false);
+        assert_eq!(image[(0, 0)], [128, 128, 128, 0xFF]);
+    }
+
+    #[test]
+    fn midpoint_circle_plots_each_perimeter_pixel_once() {
+        assert_eq!(midpoint_circle(3, 4, 0), [(3, 4)]);
+        assert_eq!(midpoint_circle(0, 0, 1), [(-1, 0), (0, -1), (0, 1), (1, 0)]);
+        assert!(midpoint_circle(0, 0, -1).is_empty());
+
+        for radius in 2..100 {
+            let points = midpoint_circle(10, -20, radius);
+            let unique: std::collections::HashSet<_> = points.iter().collect();
+            assert_eq!(unique.len(), points.len());
+
+            // Steps along the diagonals cover more of the perimeter than steps along the axes,
+            // so an eight-connected ring has about 2*sqrt(2)/pi, or 90%, of the perimeter in pixels.
+            let perimeter = 2.0*std::f64::consts::PI*radius as f64;
+            let ratio = points.len() as f64/perimeter;
+            assert!(ratio > 0.84 && ratio <= 1.0, "radius {}: {} pixels", radius, points.len());
+
+            for &(x, y) in &points {
+                let distance = (((x - 10).pow(2) + (y + 20).pow(2)) as f64).sqrt();
+                assert!((distance - radius as f64).abs() < 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn rotate90_four_times_gives_the_original() {
+        let data = (0..6*4).map(|i| i as u8).collect();
+        let image = SimpleImage::from_bgra(3, 2, data).unwrap();
+
+        let rotated = image.rotate90(1);
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+        // The bottom left pixel ends up in the top left corner.
+        assert_eq!(rotated[(0, 0)], image[(0, 1)]);
+
+        let rotated = rotated.rotate90(1).rotate90(1).rotate90(1);
+        assert_eq!((rotated.width(), rotated.height()), (3, 2));
+        assert_eq!(&*rotated, &*image);
+        assert_eq!(&*image.rotate90(4), &*image);
+    }
+
+    #[test]
+    fn sample_bilinear_between_two_pixels() {
+        let image = SimpleImage::from_bgra(2, 1, vec![0, 0, 0, 0, 200, 100, 50, 255]).unwrap();
+        assert_eq!(image.sample_bilinear(0.0, 0.0), [0, 0, 0, 0]);
+        assert_eq!(image.sample_bilinear(0.5, 0.0), [100, 50, 25, 128]);
+        assert_eq!(image.sample_bilinear(0.25, 0.0), [50, 25, 13, 64]);
+        // Positions outside of the image are clamped to the edge.
+        assert_eq!(image.sample_bilinear(3.0, -1.0), [200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn reproject_to_the_same_projection_keeps_the_image() {
+        let data = (0..16*8*4).map(|i| (i*7 % 256) as u8).collect();
+        let image = SimpleImage::from_bgra(16, 8, data).unwrap();
+
+        for projection in [Projection::Equirectangular, Projection::WebMercator] {
+            let reprojected = image.reproject(projection, projection, 16, 8);
+            assert!(reprojected.approx_equal(&image, 0), "{:?}", projection);
+        }
+    }
+
+    #[test]
+    fn blit_scaled_keeps_the_corners() {
+        let corners = [[10, 0, 0, 255], [0, 20, 0, 255], [0, 0, 30, 255], [40, 40, 40, 255]];
+        let image = SimpleImage::from_bgra(2, 2, corners.concat()).unwrap();
+
+        let mut scaled = SimpleImage::new(6, 6);
+        scaled.blit_scaled(&image, Rect::new(1, 1, 4, 4));
+        assert_eq!(scaled[(1, 1)], corners[0]);
+        assert_eq!(scaled[(4, 1)], corners[1]);
+        assert_eq!(scaled[(1, 4)], corners[2]);
+        assert_eq!(scaled[(4, 4)], corners[3]);
+        // A quarter of the way from the first pixel to the second.
+        assert_eq!(scaled[(2, 1)], [8, 5, 0, 255]);
+        // Pixels outside of the rectangle are left alone.
+        assert_eq!(scaled[(0, 0)], [0; 4]);
+        assert_eq!(scaled[(5, 5)], [0; 4]);
+    }
+
+    #[test]
+    fn diff_counts_differing_pixels() {
+        let image = SimpleImage::new(3, 2);
+        let mut other = image.clone();
+        other[(0, 0)] = [0, 5, 0, 0];
+        other[(2, 1)] = [0, 0, 0, 30];
+
+        assert_eq!(image.diff(&other).unwrap(), ImageDiff { max_difference: 30, differing_pixels: 2 });
+        assert_eq!(image.diff(&image).unwrap(), ImageDiff { max_difference: 0, differing_pixels: 0 });
+        assert!(image.diff(&SimpleImage::new(2, 3)).is_err());
+    }
+
+    #[test]
+    fn histogram_counts_each_channel() {
+        let image = SimpleImage::from_bgra(3, 1, vec![
+            1, 2, 3, 255,
+            1, 0, 3, 255,
+            9, 0, 3, 0,
+        ]).unwrap();
+
+        let histogram = image.histogram();
+        assert_eq!((histogram[0][1], histogram[0][9]), (2, 1));
+        assert_eq!((histogram[1][0], histogram[1][2]), (2, 1));
+        assert_eq!(histogram[2][3], 3);
+        assert_eq!((histogram[3][0], histogram[3][255]), (1, 2));
+        for channel in histogram {
+            assert_eq!(channel.iter().sum::<u32>(), 3);
+        }
+    }
+
+    #[test]
+    fn sub_image_mut_draws_in_local_coordinates() {
+        let mut image = SimpleImage::new(4, 3);
+        {
+            let mut region = image.sub_image_mut(2, 1, 10, 10);
+            assert_eq!((region.width(), region.height()), (2, 2));
+            region.fill([1; 4]);
+            region.set_pixel(0, 0, [2; 4]);
+            region.set_pixel(2, 0, [3; 4]);
+            region.set_pixel(-1, 0, [3; 4]);
+        }
+
+        assert_eq!(image[(2, 1)], [2; 4]);
+        assert_eq!(image[(3, 2)], [1; 4]);
+        assert_eq!(image[(1, 1)], [0; 4]);
+        assert_eq!(image[(2, 0)], [0; 4]);
+        assert_eq!(image.data.iter().filter(|&&v| v == 3).count(), 0);
+    }
+
+    #[test]
+    fn set_clip_restricts_drawing() {
+        let mut image = SimpleImage::new(4, 4);
+        image.set_clip(Some(Rect::new(1, 1, 2, 2)));
+        image.fill_rect(Rect::new(0, 0, 4, 4), [0xFF; 4]);
+        image.set_pixel(0, 3, [0xFF; 4]);
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let inside = (1..3).contains(&x) && (1..3).contains(&y);
+                assert_eq!(image[(x, y)] == [0xFF; 4], inside, "({}, {})", x, y);
+            }
+        }
+
+        image.set_clip(None);
+        image.set_pixel(0, 3, [0xFF; 4]);
+        assert_eq!(image[(0, 3)], [0xFF; 4]);
+    }
+
+    #[test]
+    fn draw_tiled_repeats_from_the_region_corner() {
+        let tile = SimpleImage::from_bgra(2, 1, [[1; 4], [2; 4]].concat()).unwrap();
+        let mut image = SimpleImage::new(6, 2);
+        image.draw_tiled(&tile, Rect::new(1, 0, 5, 1));
+
+        let row: Vec<[u8; 4]> = (0..6).map(|x| image[(x, 0)]).collect();
+        assert_eq!(row, [[0; 4], [1; 4], [2; 4], [1; 4], [2; 4], [1; 4]]);
+        assert_eq!(image[(1, 1)], [0; 4]);
+    }
+
+    #[test]
+    fn draw_polygon_textured_repeats_from_the_image_corner() {
+        let texture = SimpleImage::from_bgra(2, 1, [[1; 4], [2; 4]].concat()).unwrap();
+        let square = [Point::new(1.0, 1.0), Point::new(5.0, 1.0), Point::new(5.0, 5.0), Point::new(1.0, 5.0)];
+
+        let mut image = SimpleImage::new(8, 8);
+        image.draw_polygon_textured(&square, &texture);
+
+        let mut filled = SimpleImage::new(8, 8);
+        filled.fill_polygon(&square, [0xFF; 4]);
+        for y in 0..8 {
+            for x in 0..8 {
+                let expected = if filled[(x, y)] == [0; 4] {
+                    [0; 4]
+                } else {
+                    texture[(x % 2, 0)]
+                };
+                assert_eq!(image[(x, y)], expected, "({}, {})", x, y);
+            }
+        }
+        assert_eq!(image[(2, 2)], [1; 4]);
+        assert_eq!(image[(3, 2)], [2; 4]);
+    }
+
+    #[test]
+    fn draw_dashed_line_alternates_dashes_and_gaps() {
+        let mut image = SimpleImage::new(10, 1);
+        image.draw_dashed_line(Point::new(0.0, 0.0), Point::new(9.0, 0.0), [0xFF; 4], 2.0, 1.0);
+
+        let drawn: Vec<bool> = (0..10).map(|x| image[(x, 0)] == [0xFF; 4]).collect();
+        assert_eq!(drawn, [true, true, false, true, true, false, true, true, false, true]);
+
+        // Without a period nothing is drawn.
+        let mut image = SimpleImage::new(10, 1);
+        image.draw_dashed_line(Point::new(0.0, 0.0), Point::new(9.0, 0.0), [0xFF; 4], 0.0, 0.0);
+        assert_eq!(image.histogram()[3][0], 10);
+    }
+
+    #[test]
+    fn rgba_round_trips_through_bgra() {
+        let rgba = vec![0x11, 0x22, 0x33, 0x44, 0xAA, 0xBB, 0xCC, 0xDD];
+        let image = SimpleImage::from_rgba(2, 1, rgba.clone()).unwrap();
+
+        assert_eq!(&*image, &[0x33, 0x22, 0x11, 0x44, 0xCC, 0xBB, 0xAA, 0xDD][..]);
+        assert_eq!(image.to_rgba(), rgba);
+        assert!(SimpleImage::from_rgba(2, 2, rgba).is_err());
+    }
 }
@@ -1,9 +1,13 @@
 pub mod gui;
+pub mod svg;
+pub mod tile;
+pub mod utm;
 
 pub const MEAN_EARTH_RADIUS: f64 = 6371008.8;
 
 /// Represents a point on a two-dimensional plane.
-#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+/// The default point is at (0, 0).
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -21,6 +25,9 @@ impl Point {
     /// Convert the point to a coordinate of a place on the surface of the earth.
     /// Provide the width and height of the earth projection this object is representing a point on.
     /// A point at (0, 0) is at the top left of the map projection.
+    ///
+    /// A width or height of 1 or less gives infinite or NaN components,
+    /// use `coordinate_clamped` if that may happen.
     pub fn coordinate(&self, width: f64, height: f64) -> Coordinate {
         let lon = 180.0*(2.0*self.x/(width-1.0) - 1.0);
         let lat = 90.0*(1.0 - 2.0*self.y/(height-1.0));
@@ -28,30 +35,131 @@ impl Point {
         Coordinate([lon, lat])
     }
 
+    /// Convert the point to a coordinate like `coordinate`, but for a map with its origin at the
+    /// bottom left instead, so a point at y = 0 is on the bottom row, at latitude -90.
+    /// The inverse of `Coordinate::screen_flipped`.
+    ///
+    /// Only this method and `screen_flipped` use the bottom left origin, the other conversions
+    /// and all drawing methods keep the origin at the top left. Use `flip_y` to move points
+    /// between the two.
+    pub fn coordinate_flipped(&self, width: f64, height: f64) -> Coordinate {
+        self.flip_y(height).coordinate(width, height)
+    }
+
+    /// Mirror the point vertically on a map of the given height, which moves it between a map
+    /// with its origin at the top left, like this crate uses, and one with its origin at the bottom left.
+    pub fn flip_y(&self, height: f64) -> Point {
+        Point { x: self.x, y: height - 1.0 - self.y }
+    }
+
+    /// Convert the point to a coordinate like `coordinate`,
+    /// but get None instead of a coordinate with NaN or infinite components.
+    pub fn checked_coordinate(&self, width: f64, height: f64) -> Option<Coordinate> {
+        Some(self.coordinate(width, height)).filter(|c| self.is_finite() && c.is_finite())
+    }
+
+    /// Check that neither component is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
+    /// Convert the point to a coordinate like `coordinate`, but always get a valid coordinate.
+    ///
+    /// Along a side of the projection that is 1 pixel or less, such as a window being resized,
+    /// the coordinate is at the center of the map. Points outside of the projection are clamped
+    /// to the nearest edge.
+    pub fn coordinate_clamped(&self, width: f64, height: f64) -> Coordinate {
+        let lon = if width > 1.0 { 180.0*(2.0*self.x/(width-1.0) - 1.0) } else { 0.0 };
+        let lat = if height > 1.0 { 90.0*(1.0 - 2.0*self.y/(height-1.0)) } else { 0.0 };
+
+        Coordinate([lon.clamp(-180.0, 180.0), lat.clamp(-90.0, 90.0)])
+    }
+
+    /// Convert the point to a coordinate, like `coordinate`, but for a map drawn in the given projection.
+    /// Points outside of the area covered by the projection give coordinates outside of the
+    /// valid range of longitudes and latitudes.
+    pub fn coordinate_with(&self, projection: Projection, width: f64, height: f64) -> Coordinate {
+        let nx = 2.0*self.x/(width-1.0) - 1.0;
+        let ny = 1.0 - 2.0*self.y/(height-1.0);
+        let (lon, lat) = projection.inverse(nx, ny);
+
+        Coordinate([lon, lat])
+    }
+
     /// Calculate the distance between two points.
     pub fn distance(&self, rhs: Point) -> f64 {
         ((self.x - rhs.x).powi(2) + (self.y - rhs.y).powi(2)).sqrt()
     }
+
+    /// Get the point halfway between two points, for example to place a label between them.
+    pub fn midpoint(&self, rhs: Point) -> Point {
+        Point::new((self.x + rhs.x)/2.0, (self.y + rhs.y)/2.0)
+    }
+
+    /// Rotate the point around a pivot point by the given angle in radians.
+    /// Positive angles rotate from the positive x axis towards the positive y axis,
+    /// which is clockwise on screen, where y increases downwards.
+    pub fn rotate_around(&self, pivot: Point, radians: f64) -> Point {
+        let (sin, cos) = radians.sin_cos();
+        let dx = self.x - pivot.x;
+        let dy = self.y - pivot.y;
+
+        Point {
+            x: pivot.x + dx*cos - dy*sin,
+            y: pivot.y + dx*sin + dy*cos,
+        }
+    }
 }
 
 /// Represents a position on earth as a longitude and a latitude
-#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, serde::Serialize, serde::Deserialize)]
+///
+/// The default coordinate is at longitude 0 and latitude 0, in the Gulf of Guinea off the coast of Africa.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct Coordinate([f64; 2]);
 
 impl Coordinate {
     /// Create a new coordinate from a given longitude and latitude.
+    /// Note that the longitude comes first; prefer `from_lat_lon` if that ordering is easy to mix up.
     pub fn new(lon: f64, lat: f64) -> Self {
         Coordinate([ lon, lat ])
     }
 
+    /// Create a new coordinate from a given latitude and longitude, in that order.
+    /// ```
+    /// // Stockholm
+    /// let coordinate = Coordinate::from_lat_lon(59.33, 18.07);
+    /// assert_eq!(coordinate.lat(), 59.33);
+    /// ```
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Self {
+        Coordinate([ lon, lat ])
+    }
+
     /// Getter for the longitude component
     pub fn lon(&self) -> f64 { self.0[0] }
     /// Getter for the latitude component
     pub fn lat(&self) -> f64 { self.0[1] }
 
+    /// Get the antipode of this coordinate, the point on the exact opposite side of the earth.
+    pub fn antipode(&self) -> Coordinate {
+        Coordinate([wrap_longitude(self.lon() + 180.0), -self.lat()])
+    }
+
+    /// Get the same place as this coordinate, with its longitude shifted by whole turns to be
+    /// within 180 degrees of the longitude of `reference`, which may itself be outside -180 to 180.
+    ///
+    /// Useful for drawing a map that has been panned past the antimeridian: with a view centered
+    /// on longitude 200, a coordinate at -170 is drawn at 190, next to it, instead of far away.
+    pub fn wrap_longitude_near(&self, reference: Coordinate) -> Coordinate {
+        let lon = reference.lon() + (self.lon() - reference.lon() + 180.0).rem_euclid(360.0) - 180.0;
+        Coordinate([lon, self.lat()])
+    }
+
     /// Convert the coordinate to a point.
     /// Provide the width and the height of the projection.
     /// A point at (0, 0) is at the top left of the map projection.
+    ///
+    /// NaN or infinite components give a point with NaN or infinite components,
+    /// use `checked_screen` to catch those before drawing.
     pub fn screen(&self, width: f64, height: f64) -> Point {
         let x = (width-1.0)*(self.lon()/180.0 + 1.0)/2.0;
         let y = (height-1.0)*(1.0 - self.lat()/90.0)/2.0;
@@ -59,6 +167,36 @@ impl Coordinate {
         Point { x, y }
     }
 
+    /// Convert the coordinate to a point like `screen`, but for a map with its origin at the
+    /// bottom left instead, such as imagery or data where y grows upward.
+    /// Latitude 90 is on the top row, at y = height - 1, and latitude -90 is at y = 0.
+    ///
+    /// See `Point::coordinate_flipped` for the inverse, and for which methods are affected.
+    pub fn screen_flipped(&self, width: f64, height: f64) -> Point {
+        self.screen(width, height).flip_y(height)
+    }
+
+    /// Convert the coordinate to a point like `screen`,
+    /// but get None instead of a point with NaN or infinite components.
+    pub fn checked_screen(&self, width: f64, height: f64) -> Option<Point> {
+        Some(self.screen(width, height)).filter(|p| self.is_finite() && p.is_finite())
+    }
+
+    /// Check that neither the longitude nor the latitude is NaN or infinite,
+    /// for example to filter out coordinates from bad data before drawing them.
+    pub fn is_finite(&self) -> bool {
+        self.lon().is_finite() && self.lat().is_finite()
+    }
+
+    /// Convert the coordinate to a point, like `screen`, but for a map drawn in the given projection.
+    pub fn screen_with(&self, projection: Projection, width: f64, height: f64) -> Point {
+        let (nx, ny) = projection.forward(self.lon(), self.lat());
+        let x = (width-1.0)*(nx + 1.0)/2.0;
+        let y = (height-1.0)*(1.0 - ny)/2.0;
+
+        Point { x, y }
+    }
+
     /// Find the distance between two coordinates on earth, by the most direct line on the surface, in meters.
     /// Tedius to implement and test, so this borrows from
     /// https://docs.rs/geo/0.11.0/geo/algorithm/haversine_distance/trait.HaversineDistance.html
@@ -74,5 +212,1145 @@ impl Coordinate {
         let c = 2.0 * a.sqrt().asin();
         MEAN_EARTH_RADIUS * c
     }
+
+    /// Find the initial bearing of the great circle path from this coordinate to another,
+    /// in degrees clockwise from north, from 0 up to 360.
+    pub fn bearing(&self, rhs: Coordinate) -> f64 {
+        let phi1 = self.lat().to_radians();
+        let phi2 = rhs.lat().to_radians();
+        let delta_lambda = (rhs.lon() - self.lon()).to_radians();
+        let y = delta_lambda.sin() * phi2.cos();
+        let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * delta_lambda.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Find the coordinate reached by travelling the given distance in meters along a great
+    /// circle, starting from this coordinate with the given bearing in degrees clockwise from north.
+    pub fn destination(&self, bearing: f64, distance: f64) -> Coordinate {
+        let phi1 = self.lat().to_radians();
+        let lambda1 = self.lon().to_radians();
+        let theta = bearing.to_radians();
+        let delta = distance/MEAN_EARTH_RADIUS;
+
+        let phi2 = (phi1.sin()*delta.cos() + phi1.cos()*delta.sin()*theta.cos()).asin();
+        let lambda2 = lambda1 + (theta.sin()*delta.sin()*phi1.cos())
+            .atan2(delta.cos() - phi1.sin()*phi2.sin());
+
+        Coordinate([wrap_longitude(lambda2.to_degrees()), phi2.to_degrees()])
+    }
+
+    /// Walk the great circle path from this coordinate towards another, and return a coordinate
+    /// every `interval` meters along it, starting with this coordinate.
+    ///
+    /// Only coordinates strictly before the end are returned, so `rhs` itself is never included,
+    /// even when the distance is a whole number of intervals.
+    pub fn waypoints_every(&self, rhs: Coordinate, interval: f64) -> Vec<Coordinate> {
+        if interval <= 0.0 {
+            return vec![*self];
+        }
+
+        let total = self.great_circle_distance(rhs);
+        let bearing = self.bearing(rhs);
+        let mut waypoints = vec![*self];
+        let mut i = 1;
+        while (i as f64)*interval < total {
+            waypoints.push(self.destination(bearing, i as f64*interval));
+            i += 1;
+        }
+        waypoints
+    }
+
+    /// Get `n` coordinates evenly spaced along the great circle path from this coordinate to
+    /// another, including both ends, for example to draw the path as a polyline.
+    /// The path between two antipodal coordinates is undefined, since every great circle
+    /// through one passes through the other.
+    pub fn interpolate_n(&self, rhs: Coordinate, n: usize) -> Vec<Coordinate> {
+        if n < 2 {
+            return vec![*self; n];
+        }
+
+        let (a, b) = (unit_vector(*self), unit_vector(rhs));
+        let omega = angle(a, b);
+        (0..n)
+            .map(|i| {
+                let t = i as f64/(n - 1) as f64;
+                if i == n - 1 {
+                    return rhs;
+                }
+
+                // Spherical linear interpolation, or linear for coordinates very close together.
+                let (wa, wb) = if omega.sin().abs() < 1e-12 {
+                    (1.0 - t, t)
+                } else {
+                    (((1.0 - t)*omega).sin()/omega.sin(), (t*omega).sin()/omega.sin())
+                };
+                let v = [wa*a[0] + wb*b[0], wa*a[1] + wb*b[1], wa*a[2] + wb*b[2]];
+                vector_coordinate(v).unwrap_or(*self)
+            })
+            .collect()
+    }
+
+    /// Convert the coordinate to meters east and north of an origin, on a flat plane touching
+    /// the earth at the origin. Within a few kilometers of the origin, distances and angles on
+    /// this plane match those on the ground closely, so planar geometry can be used there.
+    pub fn to_enu(&self, origin: Coordinate) -> (f64, f64) {
+        let delta_lon = (self.lon() - origin.lon() + 180.0).rem_euclid(360.0) - 180.0;
+        let delta_lat = self.lat() - origin.lat();
+        let east = delta_lon.to_radians()*origin.lat().to_radians().cos()*MEAN_EARTH_RADIUS;
+        let north = delta_lat.to_radians()*MEAN_EARTH_RADIUS;
+        (east, north)
+    }
+
+    /// Find the coordinate at the given meters east and north of an origin. The inverse of `to_enu`.
+    pub fn from_enu(origin: Coordinate, east: f64, north: f64) -> Coordinate {
+        let lat = origin.lat() + (north/MEAN_EARTH_RADIUS).to_degrees();
+        let lon = origin.lon() + (east/(MEAN_EARTH_RADIUS*origin.lat().to_radians().cos())).to_degrees();
+        Coordinate([wrap_longitude(lon), lat])
+    }
+
+    /// Round the longitude and the latitude to the nearest multiples of a cell size in degrees,
+    /// so that nearby coordinates end up at the same place, for example to aggregate points
+    /// for a heatmap or to hide exact locations. A cell size of 0 leaves the coordinate unchanged.
+    ///
+    /// Example
+    /// ```
+    /// let snapped = Coordinate::new(18.072, 59.337).snap_to_grid(0.1);
+    /// // snapped is close to Coordinate::new(18.1, 59.3)
+    /// ```
+    pub fn snap_to_grid(&self, cell_deg: f64) -> Coordinate {
+        if cell_deg == 0.0 {
+            return *self;
+        }
+
+        let lon = (self.lon()/cell_deg).round()*cell_deg;
+        let lat = (self.lat()/cell_deg).round()*cell_deg;
+        Coordinate([wrap_longitude(lon), lat.clamp(-90.0, 90.0)])
+    }
+
+    /// Snap the coordinate to a grid with cells of roughly the given size in meters, like
+    /// `snap_to_grid`. The latitude is rounded first, and the longitude is rounded to cells
+    /// that are as wide in meters at the rounded latitude, so cells keep their size toward
+    /// the poles.
+    pub fn snap_to_grid_meters(&self, cell_m: f64) -> Coordinate {
+        let lat_cell = (cell_m/MEAN_EARTH_RADIUS).to_degrees();
+        let lat = ((self.lat()/lat_cell).round()*lat_cell).clamp(-90.0, 90.0);
+        let lon_cell = lat_cell/lat.to_radians().cos().max(1e-9);
+        let lon = (self.lon()/lon_cell).round()*lon_cell;
+        Coordinate([wrap_longitude(lon), lat])
+    }
+
+    /// Find the distance between two coordinates on earth along a rhumb line, in meters.
+    /// A rhumb line crosses every meridian at the same angle, so it is a straight line on a
+    /// mercator map, but it is longer than the great circle distance.
+    ///
+    /// The rhumb line crossing the antimeridian is used when it is the shorter way around.
+    pub fn rhumb_distance(&self, rhs: Coordinate) -> f64 {
+        let (delta_phi, delta_psi, delta_lambda) = self.rhumb_deltas(rhs);
+        let phi1 = self.lat().to_radians();
+
+        // Along an east-west line the stretched latitude difference is zero,
+        // so use the cosine of the latitude directly.
+        let q = if delta_psi.abs() > 1e-12 { delta_phi/delta_psi } else { phi1.cos() };
+        MEAN_EARTH_RADIUS * (delta_phi.powi(2) + q.powi(2)*delta_lambda.powi(2)).sqrt()
+    }
+
+    /// Find the constant bearing of the rhumb line from this coordinate to another,
+    /// in degrees clockwise from north, from 0 up to 360.
+    pub fn rhumb_bearing(&self, rhs: Coordinate) -> f64 {
+        let (_, delta_psi, delta_lambda) = self.rhumb_deltas(rhs);
+        delta_lambda.atan2(delta_psi).to_degrees().rem_euclid(360.0)
+    }
+
+    /// The differences in latitude, stretched mercator latitude, and longitude between two
+    /// coordinates, in radians. The longitude difference is the shorter way around.
+    fn rhumb_deltas(&self, rhs: Coordinate) -> (f64, f64, f64) {
+        use std::f64::consts::{FRAC_PI_4, PI};
+
+        let phi1 = self.lat().to_radians();
+        let phi2 = rhs.lat().to_radians();
+        let delta_phi = phi2 - phi1;
+        let delta_psi = ((FRAC_PI_4 + phi2/2.0).tan()/(FRAC_PI_4 + phi1/2.0).tan()).ln();
+        let delta_lambda = (rhs.lon() - self.lon()).to_radians();
+        let delta_lambda = (delta_lambda + PI).rem_euclid(2.0*PI) - PI;
+
+        (delta_phi, delta_psi, delta_lambda)
+    }
+
+    /// Format the coordinate for display, latitude first, with hemisphere letters instead of signs.
+    ///
+    /// Example
+    /// ```
+    /// let stockholm = Coordinate::new(18.072, 59.337);
+    /// stockholm.format(CoordFormat::Decimal(3)); // "59.337°N, 18.072°E"
+    /// stockholm.format(CoordFormat::DDM);        // "59°20.220'N, 18°04.320'E"
+    /// stockholm.format(CoordFormat::DMS);        // "59°20'13.2\"N, 18°04'19.2\"E"
+    /// ```
+    pub fn format(&self, fmt: CoordFormat) -> String {
+        format!(
+            "{}, {}",
+            format_angle(self.lat(), fmt, 'N', 'S'),
+            format_angle(self.lon(), fmt, 'E', 'W'),
+        )
+    }
+}
+
+/// A notation to format coordinates in with `Coordinate::format`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CoordFormat {
+    /// Decimal degrees, with the given number of decimals.
+    Decimal(usize),
+    /// Whole degrees and decimal minutes, with three decimals, as used in marine navigation.
+    DDM,
+    /// Whole degrees, whole minutes and seconds with one decimal.
+    DMS,
+}
+
+/// Format one angle of a coordinate, with the letter for its hemisphere.
+fn format_angle(angle: f64, fmt: CoordFormat, positive: char, negative: char) -> String {
+    let hemisphere = if angle < 0.0 { negative } else { positive };
+    let angle = angle.abs();
+    // Round once in the smallest unit shown, so that for example 59.99999 becomes 60°00' and not 59°60'.
+    match fmt {
+        CoordFormat::Decimal(precision) => format!("{:.*}°{}", precision, angle, hemisphere),
+        CoordFormat::DDM => {
+            let thousandths = (angle*60000.0).round() as u64;
+            let minutes = thousandths%60000;
+            format!("{}°{:02}.{:03}'{}", thousandths/60000, minutes/1000, minutes%1000, hemisphere)
+        }
+        CoordFormat::DMS => {
+            let tenths = (angle*36000.0).round() as u64;
+            let seconds = tenths%600;
+            format!("{}°{:02}'{:02}.{}\"{}", tenths/36000, tenths%36000/600, seconds/10, seconds%10, hemisphere)
+        }
+    }
+}
+
+/// Convert every coordinate to a point with `Coordinate::screen`,
+/// for example to draw a ring of coordinates with `draw_polygon`.
+pub fn project_all(coords: &[Coordinate], width: f64, height: f64) -> Vec<Point> {
+    coords.iter().map(|c| c.screen(width, height)).collect()
+}
+
+/// Convert every coordinate to a point like `project_all`, but into an existing buffer,
+/// which is cleared first. Reusing the same buffer every frame avoids allocating it again,
+/// which matters when projecting large datasets.
+///
+/// The scale and offset of the projection are worked out once, so each coordinate only takes
+/// a multiplication and an addition per axis, in a loop the compiler can vectorize. Because of this,
+/// the points may differ from those of `Coordinate::screen` in the last few bits.
+///
+/// Example
+/// ```
+/// let mut points = Vec::new();
+/// window.run(points, |window, points, _| {
+///     project_into(&dataset, 800.0, 400.0, points);
+///     // draw the points
+///     Ok(())
+/// });
+/// ```
+pub fn project_into(coords: &[Coordinate], width: f64, height: f64, out: &mut Vec<Point>) {
+    let scale_x = (width - 1.0)/360.0;
+    let offset_x = (width - 1.0)/2.0;
+    let scale_y = -(height - 1.0)/180.0;
+    let offset_y = (height - 1.0)/2.0;
+
+    out.clear();
+    out.extend(coords.iter().map(|c| Point {
+        x: c.lon()*scale_x + offset_x,
+        y: c.lat()*scale_y + offset_y,
+    }));
+}
+
+/// Convert every point to a coordinate with `Point::coordinate`. The inverse of `project_all`.
+pub fn unproject_all(points: &[Point], width: f64, height: f64) -> Vec<Coordinate> {
+    points.iter().map(|p| p.coordinate(width, height)).collect()
+}
+
+/// Name the compass point closest to a bearing in degrees clockwise from north, out of the 16
+/// from "N", "NNE" and "NE" around to "NNW", for example to label `Coordinate::bearing` on screen.
+/// Any bearing is accepted, so 350 and -10 both give "N".
+pub fn cardinal_direction(bearing_deg: f64) -> &'static str {
+    const POINTS: [&str; 16] = [
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE",
+        "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW",
+    ];
+    let index = (bearing_deg.rem_euclid(360.0)/22.5).round() as usize;
+    POINTS[index % 16]
+}
+
+/// Find how many meters on the ground one pixel covers around a coordinate,
+/// for a map of the given size drawn with the given projection.
+///
+/// Measured horizontally between the two pixels on each side of the coordinate,
+/// which is what a horizontal scale bar needs. Most projections stretch the map
+/// away from the equator, so the result depends on the latitude of `center`.
+pub fn meters_per_pixel(center: Coordinate, width: f64, height: f64, projection: Projection) -> f64 {
+    let point = center.screen_with(projection, width, height);
+    let left = Point::new(point.x - 0.5, point.y).coordinate_with(projection, width, height);
+    let right = Point::new(point.x + 0.5, point.y).coordinate_with(projection, width, height);
+    left.great_circle_distance(right)
+}
+
+/// Smooth a path of coordinates, such as a GPS track, with a Catmull-Rom spline.
+///
+/// The returned path passes through every original coordinate, with `points_per_segment`
+/// coordinates from each original coordinate up to (but not including) the next one, followed
+/// by the last original coordinate. The first and last coordinates are repeated to give the
+/// spline its end conditions.
+///
+/// The spline is computed on longitude and latitude directly, which is accurate for the short
+/// segments of a track, but does not follow great circles over long distances. Paths crossing
+/// the antimeridian are handled by interpolating in a continuous range of longitudes.
+pub fn smooth_path(coords: &[Coordinate], points_per_segment: usize) -> Vec<Coordinate> {
+    if coords.len() < 2 || points_per_segment == 0 {
+        return coords.to_vec();
+    }
+
+    let mut unwrapped = vec![coords[0]];
+    for c in &coords[1..] {
+        let previous = unwrapped[unwrapped.len() - 1].lon();
+        let lon = previous + (c.lon() - previous + 180.0).rem_euclid(360.0) - 180.0;
+        unwrapped.push(Coordinate::new(lon, c.lat()));
+    }
+
+    let control = |i: isize| unwrapped[i.clamp(0, unwrapped.len() as isize - 1) as usize];
+    let catmull_rom = |p0: f64, p1: f64, p2: f64, p3: f64, t: f64| {
+        0.5*(2.0*p1
+            + (p2 - p0)*t
+            + (2.0*p0 - 5.0*p1 + 4.0*p2 - p3)*t.powi(2)
+            + (3.0*p1 - p0 - 3.0*p2 + p3)*t.powi(3))
+    };
+
+    let mut path = vec![];
+    for i in 0..unwrapped.len() as isize - 1 {
+        let (p0, p1, p2, p3) = (control(i - 1), control(i), control(i + 1), control(i + 2));
+        for k in 0..points_per_segment {
+            let t = k as f64/points_per_segment as f64;
+            let lon = catmull_rom(p0.lon(), p1.lon(), p2.lon(), p3.lon(), t);
+            let lat = catmull_rom(p0.lat(), p1.lat(), p2.lat(), p3.lat(), t);
+            path.push(Coordinate::new(wrap_longitude(lon), lat));
+        }
+    }
+    path.push(coords[coords.len() - 1]);
+
+    path
+}
+
+/// Wrap a longitude in degrees into the range -180 up to 180.
+fn wrap_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Check whether a point lies inside a polygon on a plane, given a list of all points that
+/// define it, using the even-odd rule. The polygon is closed automatically.
+pub fn point_in_polygon(point: Point, polygon: &[Point]) -> bool {
+    let mut inside = false;
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x + (point.y - a.y)*(b.x - a.x)/(b.y - a.y);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Check whether a ring of points goes clockwise as seen on screen, where y increases downwards.
+pub fn ring_is_clockwise(ring: &[Point]) -> bool {
+    // With y pointing down, a positive signed area means clockwise.
+    let ring: Vec<(f64, f64)> = ring.iter().map(|p| (p.x, p.y)).collect();
+    signed_area(&ring) > 0.0
+}
+
+/// Reverse a ring of points if needed, so that it goes clockwise on screen if `clockwise` is
+/// true, and counterclockwise otherwise.
+pub fn ensure_winding(ring: &mut [Point], clockwise: bool) {
+    if ring_is_clockwise(ring) != clockwise {
+        ring.reverse();
+    }
+}
+
+/// Find the smallest convex polygon containing all the points, for example to draw a boundary
+/// around a cluster. The corners are returned in order, ready for `draw_polygon`, going
+/// clockwise on screen where y points down. Points along the edges of the hull are left out.
+///
+/// With fewer than three distinct points, or all points on a line, the hull has no area,
+/// and the distinct extreme points are returned instead.
+pub fn convex_hull(points: &[Point]) -> Vec<Point> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let cross = |o: Point, a: Point, b: Point| (a.x - o.x)*(b.y - o.y) - (a.y - o.y)*(b.x - o.x);
+
+    // Andrew's monotone chain, building the lower and then the upper half of the hull.
+    let mut hull: Vec<Point> = vec![];
+    let add = |hull: &mut Vec<Point>, start: usize, p: Point| {
+        while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(p);
+    };
+    for &p in &sorted {
+        add(&mut hull, 0, p);
+    }
+    let upper = hull.len() - 1;
+    for &p in sorted.iter().rev().skip(1) {
+        add(&mut hull, upper, p);
+    }
+    hull.pop();
+
+    hull
+}
+
+/// Grow a polygon by moving every edge outward by `distance`, for example to draw a halo or a
+/// margin around a region. A negative distance shrinks the polygon instead.
+///
+/// The offset edges are extended until they meet, giving sharp corners, except where that corner
+/// would reach more than twice `distance` from the original one. Such sharp corners are cut off
+/// with an extra point instead. Shrinking a polygon by more than its thickness, or offsetting
+/// concave corners by a lot, can give edges that cross each other.
+pub fn offset_polygon(polygon: &[Point], distance: f64) -> Vec<Point> {
+    let mut ring = polygon.to_vec();
+    ring.dedup();
+    while ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    if ring.len() < 3 {
+        return ring;
+    }
+
+    // Which side is outward depends on which way the ring goes.
+    let area = signed_area(&ring.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>());
+    let side = if area > 0.0 { 1.0 } else { -1.0 };
+    let normal = |a: Point, b: Point| {
+        let length = a.distance(b);
+        Point::new(side*(b.y - a.y)/length, -side*(b.x - a.x)/length)
+    };
+
+    let n = ring.len();
+    let mut offset = vec![];
+    for i in 0..n {
+        let (prev, p, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+        let n0 = normal(prev, p);
+        let n1 = normal(p, next);
+
+        let cos = n0.x*n1.x + n0.y*n1.y;
+        if 1.0 + cos < 0.5 {
+            offset.push(Point::new(p.x + n0.x*distance, p.y + n0.y*distance));
+            offset.push(Point::new(p.x + n1.x*distance, p.y + n1.y*distance));
+        } else {
+            let scale = distance/(1.0 + cos);
+            offset.push(Point::new(p.x + (n0.x + n1.x)*scale, p.y + (n0.y + n1.y)*scale));
+        }
+    }
+
+    offset
+}
+
+/// Check whether a ring of coordinates goes clockwise as seen on a map with north up.
+/// Rings crossing the antimeridian are handled.
+pub fn coordinate_ring_is_clockwise(ring: &[Coordinate]) -> bool {
+    // With latitude pointing up, a negative signed area means clockwise.
+    signed_area(&unwrap_ring(ring)) < 0.0
+}
+
+/// Reverse a ring of coordinates if needed, so that it goes clockwise on a map with north up if
+/// `clockwise` is true, and counterclockwise otherwise.
+pub fn ensure_coordinate_winding(ring: &mut [Coordinate], clockwise: bool) {
+    if coordinate_ring_is_clockwise(ring) != clockwise {
+        ring.reverse();
+    }
+}
+
+/// Find the center of the area enclosed by a ring of coordinates, for example to place a label.
+///
+/// The centroid is computed on longitude and latitude as if they were flat, which is accurate
+/// enough for small regions but drifts for regions spanning many degrees, especially near the
+/// poles. Rings crossing the antimeridian are handled. For concave shapes, like a crescent,
+/// the centroid may fall outside of the region itself.
+pub fn ring_centroid(ring: &[Coordinate]) -> Coordinate {
+    let unwrapped = unwrap_ring(ring);
+    if unwrapped.is_empty() {
+        return Coordinate::new(0.0, 0.0);
+    }
+
+    let area = signed_area(&unwrapped);
+    let (lon, lat) = if area.abs() < f64::EPSILON {
+        // Without an area, as for a line or a single point, fall back to the average coordinate.
+        let n = unwrapped.len() as f64;
+        let (lon, lat) = unwrapped.iter()
+            .fold((0.0, 0.0), |(lon, lat), (x, y)| (lon + x, lat + y));
+        (lon/n, lat/n)
+    } else {
+        let (lon, lat) = unwrapped.iter()
+            .zip(unwrapped.iter().cycle().skip(1))
+            .fold((0.0, 0.0), |(lon, lat), ((x0, y0), (x1, y1))| {
+                let cross = x0*y1 - x1*y0;
+                (lon + (x0 + x1)*cross, lat + (y0 + y1)*cross)
+            });
+        (lon/(3.0*area), lat/(3.0*area))
+    };
+
+    Coordinate::new(wrap_longitude(lon), lat)
+}
+
+/// Get the longitude and latitude of every coordinate in a ring, with the longitudes
+/// continuing past 180 or -180 instead of jumping, for rings crossing the antimeridian.
+fn unwrap_ring(ring: &[Coordinate]) -> Vec<(f64, f64)> {
+    let mut lon = ring.first().map(|c| c.lon()).unwrap_or(0.0);
+    let mut previous = lon;
+    ring.iter().map(|c| {
+        lon += (c.lon() - previous + 180.0).rem_euclid(360.0) - 180.0;
+        previous = c.lon();
+        (lon, c.lat())
+    }).collect()
+}
+
+/// Twice the signed area of a closed ring, using the shoelace formula.
+fn signed_area(ring: &[(f64, f64)]) -> f64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|((x0, y0), (x1, y1))| x0*y1 - x1*y0)
+        .sum()
 }
 
+/// Check whether a coordinate lies inside a polygon on the surface of the earth, given a
+/// ring of all coordinates that define it. The ring must cover less than half of the earth.
+///
+/// Unlike projecting everything to the screen and using `point_in_polygon`, this works for
+/// polygons that cross the antimeridian or enclose a pole. The ring and the coordinate are
+/// projected onto a plane centered on the ring and tested there, so the edges between points
+/// follow the great circle paths closely as long as the ring has reasonably many points.
+pub fn coordinate_in_polygon(point: Coordinate, ring: &[Coordinate]) -> bool {
+    let sum = ring.iter().copied().map(unit_vector).fold([0.0; 3], |sum, v| {
+        [sum[0] + v[0], sum[1] + v[1], sum[2] + v[2]]
+    });
+    let center = match vector_coordinate(sum) {
+        Some(center) => center,
+        None => return false,
+    };
+
+    // Azimuthal equidistant projection around the center of the ring.
+    let project = |c: Coordinate| {
+        let distance = center.great_circle_distance(c);
+        let bearing = center.bearing(c).to_radians();
+        Point::new(distance*bearing.sin(), distance*bearing.cos())
+    };
+
+    let polygon: Vec<Point> = ring.iter().copied().map(project).collect();
+    point_in_polygon(project(point), &polygon)
+}
+
+/// Find where two great circle segments, from `a1` to `a2` and from `b1` to `b2`, cross each other.
+/// Returns None if the segments don't intersect, or if they lie on the same great circle.
+///
+/// Useful for detecting where two routes cross.
+pub fn segments_intersect(a1: Coordinate, a2: Coordinate, b1: Coordinate, b2: Coordinate) -> Option<Coordinate> {
+    let (a1, a2) = (unit_vector(a1), unit_vector(a2));
+    let (b1, b2) = (unit_vector(b1), unit_vector(b2));
+
+    // The great circles meet along the line perpendicular to both of their normals.
+    let line = cross(cross(a1, a2), cross(b1, b2));
+    let length = dot(line, line).sqrt();
+    if length < 1e-12 {
+        return None;
+    }
+
+    // The line crosses the sphere at two antipodal points, pick the one within both segments.
+    let on_arc = |p: [f64; 3], start: [f64; 3], end: [f64; 3]| {
+        (angle(start, p) + angle(p, end) - angle(start, end)).abs() < 1e-9
+    };
+    [1.0, -1.0].iter()
+        .map(|sign| line.map(|v| sign*v/length))
+        .find(|&p| on_arc(p, a1, a2) && on_arc(p, b1, b2))
+        .and_then(vector_coordinate)
+}
+
+/// Group coordinates that are close to each other, such as markers that would overlap
+/// when the map is zoomed out, and get the center and number of coordinates of each group.
+///
+/// Coordinates are considered in order: each one joins the first group whose first
+/// coordinate is within `radius_m` meters of it, or starts a new group if there is none.
+/// The center of a group is the average of its coordinates, on the surface of the earth,
+/// so groups spanning the antimeridian are centered correctly.
+pub fn cluster(coords: &[Coordinate], radius_m: f64) -> Vec<(Coordinate, usize)> {
+    let mut clusters: Vec<(Coordinate, [f64; 3], usize)> = vec![];
+    for &c in coords {
+        let v = unit_vector(c);
+        match clusters.iter_mut().find(|(first, _, _)| first.great_circle_distance(c) <= radius_m) {
+            Some((_, sum, count)) => {
+                *sum = [sum[0] + v[0], sum[1] + v[1], sum[2] + v[2]];
+                *count += 1;
+            },
+            None => clusters.push((c, v, 1)),
+        }
+    }
+
+    clusters.into_iter()
+        .map(|(first, sum, count)| (vector_coordinate(sum).unwrap_or(first), count))
+        .collect()
+}
+
+/// Convert a coordinate to a unit vector from the center of the earth.
+fn unit_vector(c: Coordinate) -> [f64; 3] {
+    let (lon, lat) = (c.lon().to_radians(), c.lat().to_radians());
+    [lat.cos()*lon.cos(), lat.cos()*lon.sin(), lat.sin()]
+}
+
+/// Convert a vector from the center of the earth, of any length, to the coordinate it points at.
+/// Returns None for vectors too short to have a direction.
+fn vector_coordinate(v: [f64; 3]) -> Option<Coordinate> {
+    let length = dot(v, v).sqrt();
+    if length < 1e-12 {
+        return None;
+    }
+
+    Some(Coordinate::new(
+        v[1].atan2(v[0]).to_degrees(),
+        (v[2]/length).clamp(-1.0, 1.0).asin().to_degrees(),
+    ))
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2], a[0]*b[1] - a[1]*b[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0]*b[0] + a[1]*b[1] + a[2]*b[2]
+}
+
+/// The angle in radians between two vectors.
+fn angle(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let c = cross(a, b);
+    dot(c, c).sqrt().atan2(dot(a, b))
+}
+
+/// Get a pseudo-random offset, which is always the same for the same seed and index.
+/// Both components are in the range -1 up to 1, so scale the offset to the size needed.
+///
+/// Useful for displacing labels or markers in a way that looks random,
+/// but doesn't change between frames or runs.
+pub fn jitter(seed: u64, index: usize) -> Point {
+    let unit = |n: u64| {
+        let bits = splitmix64(seed ^ splitmix64(n));
+        (bits >> 11) as f64/(1u64 << 53) as f64
+    };
+
+    let index = index as u64;
+    Point::new(
+        2.0*unit(2*index) - 1.0,
+        2.0*unit(2*index + 1) - 1.0,
+    )
+}
+
+/// Scatter `n` pseudo-random points inside a polygon, for example for a dot density map.
+/// The same seed always gives the same points, using the same generator as `jitter`.
+///
+/// Points are picked within the bounding box of the polygon and kept if they are inside it,
+/// so polygons covering little of their bounding box take longer. Polygons with no area,
+/// where hardly any attempts succeed, may give fewer than `n` points.
+pub fn random_points_in_polygon(polygon: &[Point], n: usize, seed: u64) -> Vec<Point> {
+    let bbox = match BoundingBox::from_points(polygon) {
+        Some(bbox) => bbox,
+        None => return vec![],
+    };
+
+    let center = Point::new((bbox.min_x + bbox.max_x)/2.0, (bbox.min_y + bbox.max_y)/2.0);
+    let half_width = (bbox.max_x - bbox.min_x)/2.0;
+    let half_height = (bbox.max_y - bbox.min_y)/2.0;
+
+    let max_attempts = n.saturating_mul(1000).max(1000);
+    (0..max_attempts)
+        .map(|i| {
+            let offset = jitter(seed, i);
+            Point::new(center.x + offset.x*half_width, center.y + offset.y*half_height)
+        })
+        .filter(|&p| point_in_polygon(p, polygon))
+        .take(n)
+        .collect()
+}
+
+/// One step of the SplitMix64 generator, scrambling the bits of the input.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// A way of flattening the surface of the earth onto a map.
+///
+/// Every projection fills the width and height of the map it is used for, so the aspect ratio
+/// of the map decides how stretched it looks. `Point::coordinate` and `Coordinate::screen`
+/// use the equirectangular projection, and the `_with` variants of them accept any projection.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Projection {
+    /// Longitude and latitude are mapped directly to x and y. Looks best with a 2:1 aspect ratio.
+    Equirectangular,
+    /// The projection used by most web maps. Latitudes beyond ±85.0511°, where the
+    /// projection would stretch to infinity, are cut off. Looks best with a 1:1 aspect ratio.
+    WebMercator,
+    /// An equal-area projection showing the whole earth as an ellipse.
+    /// Looks best with a 2:1 aspect ratio.
+    ///
+    /// The forward transform solves for an auxiliary angle using Newton-Raphson iteration,
+    /// which stops after 20 iterations or once an iteration changes the angle by less than
+    /// 1e-12 radians.
+    Mollweide,
+    /// An equal-area projection where every parallel keeps its true length.
+    /// Looks best with a 2:1 aspect ratio.
+    Sinusoidal,
+}
+
+impl Projection {
+    /// Latitude at which the web mercator projection is cut off, making the map square.
+    const WEB_MERCATOR_MAX_LAT: f64 = 85.05112878;
+
+    /// Project a longitude and latitude in degrees to x and y, both from -1 to 1.
+    fn forward(self, lon: f64, lat: f64) -> (f64, f64) {
+        use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+        let lambda = lon.to_radians();
+        let phi = lat.to_radians();
+        match self {
+            Projection::Equirectangular => (lon/180.0, lat/90.0),
+            Projection::WebMercator => {
+                let phi = lat.clamp(-Self::WEB_MERCATOR_MAX_LAT, Self::WEB_MERCATOR_MAX_LAT).to_radians();
+                (lambda/PI, (FRAC_PI_4 + phi/2.0).tan().ln()/PI)
+            },
+            Projection::Mollweide => {
+                let theta = mollweide_theta(phi);
+                (lambda*theta.cos()/PI, theta.sin())
+            },
+            Projection::Sinusoidal => (lambda*phi.cos()/PI, phi/FRAC_PI_2),
+        }
+    }
+
+    /// Inverse of `forward`, from x and y in the range -1 to 1 to a longitude and latitude.
+    fn inverse(self, x: f64, y: f64) -> (f64, f64) {
+        use std::f64::consts::{FRAC_PI_2, PI};
+
+        let (lambda, phi) = match self {
+            Projection::Equirectangular => return (180.0*x, 90.0*y),
+            Projection::WebMercator => (PI*x, 2.0*(PI*y).exp().atan() - FRAC_PI_2),
+            Projection::Mollweide => {
+                let theta = y.clamp(-1.0, 1.0).asin();
+                let phi = ((2.0*theta + (2.0*theta).sin())/PI).clamp(-1.0, 1.0).asin();
+                let lambda = if theta.cos() > f64::EPSILON { PI*x/theta.cos() } else { 0.0 };
+                (lambda, phi)
+            },
+            Projection::Sinusoidal => {
+                let phi = FRAC_PI_2*y;
+                let lambda = if phi.cos() > f64::EPSILON { PI*x/phi.cos() } else { 0.0 };
+                (lambda, phi)
+            },
+        };
+
+        (lambda.to_degrees(), phi.to_degrees())
+    }
+}
+
+/// Solve 2θ + sin(2θ) = π sin(φ) for the auxiliary angle θ of the Mollweide projection.
+fn mollweide_theta(phi: f64) -> f64 {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    if phi.abs() >= FRAC_PI_2 {
+        return phi.signum()*FRAC_PI_2;
+    }
+
+    // Iterate on 2θ, which converges better near the poles.
+    let target = PI*phi.sin();
+    let mut two_theta = 2.0*phi;
+    for _ in 0..20 {
+        let delta = (two_theta + two_theta.sin() - target)/(1.0 + two_theta.cos());
+        two_theta -= delta;
+        if delta.abs() < 1e-12 {
+            break;
+        }
+    }
+
+    two_theta/2.0
+}
+
+/// An axis aligned rectangle on a two-dimensional plane.
+///
+/// Boxes around coordinates use the longitude as x and the latitude as y,
+/// so a box can be used both for geographic data and for points on screen.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct BoundingBox {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl BoundingBox {
+    /// Create a new box from its smallest and largest x and y.
+    pub fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Self {
+        BoundingBox {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    /// Find the smallest box containing all the provided points, or None if there are none.
+    pub fn from_points(points: &[Point]) -> Option<Self> {
+        let first = points.first()?;
+        let mut bbox = BoundingBox::new(first.x, first.y, first.x, first.y);
+        for p in &points[1..] {
+            bbox.min_x = bbox.min_x.min(p.x);
+            bbox.min_y = bbox.min_y.min(p.y);
+            bbox.max_x = bbox.max_x.max(p.x);
+            bbox.max_y = bbox.max_y.max(p.y);
+        }
+        Some(bbox)
+    }
+
+    /// Find the smallest box containing all the provided coordinates, with the longitude as x
+    /// and the latitude as y, or None if there are none.
+    pub fn from_coordinates(coords: &[Coordinate]) -> Option<Self> {
+        let points: Vec<Point> = coords.iter().map(|c| Point::new(c.lon(), c.lat())).collect();
+        BoundingBox::from_points(&points)
+    }
+
+    /// Check whether a point lies inside the box, including its edges.
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.min_x && point.x <= self.max_x
+            && point.y >= self.min_y && point.y <= self.max_y
+    }
+
+    /// Check whether a coordinate lies inside a box of longitudes and latitudes, including its edges.
+    pub fn contains_coordinate(&self, coordinate: Coordinate) -> bool {
+        self.contains(Point::new(coordinate.lon(), coordinate.lat()))
+    }
+
+    /// Check whether another box lies entirely inside this box.
+    pub fn contains_box(&self, other: &BoundingBox) -> bool {
+        other.min_x >= self.min_x && other.max_x <= self.max_x
+            && other.min_y >= self.min_y && other.max_y <= self.max_y
+    }
+
+    /// Check whether two boxes overlap, including touching at the edges.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_x <= other.max_x && other.min_x <= self.max_x
+            && self.min_y <= other.max_y && other.min_y <= self.max_y
+    }
+}
+
+/// Keeps track of the bounding box of a stream of coordinates, such as points of a GPS track
+/// arriving one at a time, without going through all earlier coordinates again.
+///
+/// Like `BoundingBox::from_coordinates`, the box has the longitude as x and the latitude as y.
+/// Each longitude is taken as the closest way around from the previous one, so a track crossing
+/// the antimeridian gives a narrow box extending past 180 or -180, instead of one spanning the
+/// whole earth. A box that would be wider than the whole earth covers -180 to 180 instead.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct BoundsAccumulator {
+    bounds: Option<BoundingBox>,
+    lon: f64,
+}
+
+impl BoundsAccumulator {
+    /// Start without any coordinates.
+    pub fn new() -> Self {
+        BoundsAccumulator {
+            bounds: None,
+            lon: 0.0,
+        }
+    }
+
+    /// Grow the box to include another coordinate.
+    pub fn push(&mut self, coordinate: Coordinate) {
+        let lat = coordinate.lat();
+        let bounds = match &mut self.bounds {
+            Some(bounds) => bounds,
+            None => {
+                self.lon = coordinate.lon();
+                self.bounds = Some(BoundingBox::new(self.lon, lat, self.lon, lat));
+                return;
+            },
+        };
+
+        self.lon += (coordinate.lon() - self.lon + 180.0).rem_euclid(360.0) - 180.0;
+        bounds.min_x = bounds.min_x.min(self.lon);
+        bounds.max_x = bounds.max_x.max(self.lon);
+        bounds.min_y = bounds.min_y.min(lat);
+        bounds.max_y = bounds.max_y.max(lat);
+    }
+
+    /// Get the box containing all coordinates pushed so far, or None if there are none.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.bounds.map(|bounds| {
+            if bounds.max_x - bounds.min_x >= 360.0 {
+                BoundingBox::new(-180.0, bounds.min_y, 180.0, bounds.max_y)
+            } else {
+                bounds
+            }
+        })
+    }
+}
+
+/// Indexes items by their bounding boxes, to quickly find the items whose box contains a point.
+///
+/// Useful for hit testing a click against many polygons: query the point to find the few
+/// polygons whose boxes contain it, then check those with `point_in_polygon`.
+pub struct Quadtree<T> {
+    root: QuadNode<T>,
+}
+
+struct QuadNode<T> {
+    bounds: BoundingBox,
+    depth: usize,
+    items: Vec<(BoundingBox, T)>,
+    children: Vec<QuadNode<T>>,
+}
+
+impl<T> Quadtree<T> {
+    /// Items stored in a node before it is split into four
+    const MAX_ITEMS: usize = 8;
+    /// Depth after which nodes are no longer split
+    const MAX_DEPTH: usize = 8;
+
+    /// Create an empty tree covering the given area. Items outside of the area may still be
+    /// inserted, but they are not indexed as efficiently.
+    pub fn new(bounds: BoundingBox) -> Self {
+        Quadtree {
+            root: QuadNode::new(bounds, 0),
+        }
+    }
+
+    /// Add an item with the given bounding box to the tree.
+    pub fn insert(&mut self, bbox: BoundingBox, item: T) {
+        self.root.insert(bbox, item);
+    }
+
+    /// Find all items whose bounding box contains the point.
+    pub fn query_point(&self, point: Point) -> Vec<&T> {
+        let mut found = vec![];
+        self.root.query_point(point, &mut found);
+        found
+    }
+}
+
+impl<T> QuadNode<T> {
+    fn new(bounds: BoundingBox, depth: usize) -> Self {
+        QuadNode {
+            bounds,
+            depth,
+            items: vec![],
+            children: vec![],
+        }
+    }
+
+    fn insert(&mut self, bbox: BoundingBox, item: T) {
+        if let Some(child) = self.children.iter_mut().find(|c| c.bounds.contains_box(&bbox)) {
+            child.insert(bbox, item);
+            return;
+        }
+
+        self.items.push((bbox, item));
+        if self.children.is_empty()
+            && self.items.len() > Quadtree::<T>::MAX_ITEMS
+            && self.depth < Quadtree::<T>::MAX_DEPTH {
+            self.split();
+        }
+    }
+
+    fn split(&mut self) {
+        let b = self.bounds;
+        let mid_x = (b.min_x + b.max_x)/2.0;
+        let mid_y = (b.min_y + b.max_y)/2.0;
+        self.children = vec![
+            QuadNode::new(BoundingBox::new(b.min_x, b.min_y, mid_x, mid_y), self.depth + 1),
+            QuadNode::new(BoundingBox::new(mid_x, b.min_y, b.max_x, mid_y), self.depth + 1),
+            QuadNode::new(BoundingBox::new(b.min_x, mid_y, mid_x, b.max_y), self.depth + 1),
+            QuadNode::new(BoundingBox::new(mid_x, mid_y, b.max_x, b.max_y), self.depth + 1),
+        ];
+
+        for (bbox, item) in std::mem::take(&mut self.items) {
+            self.insert(bbox, item);
+        }
+    }
+
+    fn query_point<'a>(&'a self, point: Point, found: &mut Vec<&'a T>) {
+        for (bbox, item) in &self.items {
+            if bbox.contains(point) {
+                found.push(item);
+            }
+        }
+
+        for child in &self.children {
+            if child.bounds.contains(point) {
+                child.query_point(point, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_area_projections_round_trip() {
+        for projection in [Projection::Mollweide, Projection::Sinusoidal] {
+            for lat in (-80..=80).step_by(10) {
+                for lon in (-170..=170).step_by(10) {
+                    let coordinate = Coordinate::new(lon as f64, lat as f64);
+                    let back = coordinate.screen_with(projection, 800.0, 400.0)
+                        .coordinate_with(projection, 800.0, 400.0);
+                    assert!(
+                        (back.lon() - coordinate.lon()).abs() < 1e-9 && (back.lat() - coordinate.lat()).abs() < 1e-9,
+                        "{:?} gave {:?} back for {:?}", projection, back, coordinate,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quadtree_finds_exactly_the_boxes_containing_a_point() {
+        // A grid of 10 by 10 unit boxes is enough items to split the tree a few times.
+        let mut boxes = vec![];
+        for y in 0..10 {
+            for x in 0..10 {
+                boxes.push(BoundingBox::new(x as f64, y as f64, x as f64 + 1.0, y as f64 + 1.0));
+            }
+        }
+        let mut tree = Quadtree::new(BoundingBox::new(0.0, 0.0, 10.0, 10.0));
+        for (i, bbox) in boxes.iter().enumerate() {
+            tree.insert(*bbox, i);
+        }
+
+        // Inside a box, on an edge between two, on a corner between four,
+        // on the lines the tree splits along, and on the edge of the whole tree.
+        let points = [(2.5, 7.5), (3.0, 4.5), (3.0, 4.0), (5.0, 5.0), (5.0, 2.5), (10.0, 0.0), (0.0, 6.25)];
+        for (x, y) in points {
+            let point = Point::new(x, y);
+            let mut found: Vec<usize> = tree.query_point(point).into_iter().copied().collect();
+            found.sort();
+            let expected: Vec<usize> = (0..boxes.len()).filter(|&i| boxes[i].contains(point)).collect();
+            assert_eq!(found, expected, "at {:?}", point);
+        }
+        assert_eq!(tree.query_point(Point::new(3.0, 4.0)).len(), 4);
+        assert!(tree.query_point(Point::new(10.5, 5.0)).is_empty());
+    }
+
+    #[test]
+    fn convex_hull_keeps_only_corners_clockwise() {
+        let points = [
+            Point::new(0.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(4.0, 0.0),
+            Point::new(4.0, 4.0),
+            Point::new(0.0, 4.0),
+            Point::new(0.0, 2.0),
+            Point::new(1.0, 3.0),
+            Point::new(4.0, 0.0),
+        ];
+        let hull = convex_hull(&points);
+
+        assert_eq!(hull.len(), 4);
+        for (x, y) in [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)] {
+            assert!(hull.contains(&Point::new(x, y)), "({}, {}) is missing from {:?}", x, y, hull);
+        }
+        assert!(ring_is_clockwise(&hull));
+    }
+
+    #[test]
+    fn offset_square_grows_either_winding() {
+        let mut square = vec![
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(10.0, 10.0),
+            Point::new(0.0, 10.0),
+        ];
+        for _ in 0..2 {
+            let grown = offset_polygon(&square, 1.0);
+            let bbox = BoundingBox::from_points(&grown).unwrap();
+            assert_eq!(grown.len(), 4);
+            assert!((bbox.min_x + 1.0).abs() < 1e-9 && (bbox.min_y + 1.0).abs() < 1e-9);
+            assert!((bbox.max_x - 11.0).abs() < 1e-9 && (bbox.max_y - 11.0).abs() < 1e-9);
+            for p in &grown {
+                assert!((p.x + 1.0).abs() < 1e-9 || (p.x - 11.0).abs() < 1e-9);
+                assert!((p.y + 1.0).abs() < 1e-9 || (p.y - 11.0).abs() < 1e-9);
+            }
+            square.reverse();
+        }
+    }
+
+    #[test]
+    fn format_notations() {
+        let stockholm = Coordinate::new(18.072, 59.337);
+        assert_eq!(stockholm.format(CoordFormat::Decimal(3)), "59.337°N, 18.072°E");
+        assert_eq!(stockholm.format(CoordFormat::DDM), "59°20.220'N, 18°04.320'E");
+        assert_eq!(stockholm.format(CoordFormat::DMS), "59°20'13.2\"N, 18°04'19.2\"E");
+
+        // Rounding carries over into the minutes and degrees, instead of showing 60.
+        let rounded = Coordinate::new(-0.5, -59.999999);
+        assert_eq!(rounded.format(CoordFormat::DMS), "60°00'00.0\"S, 0°30'00.0\"W");
+        assert_eq!(rounded.format(CoordFormat::DDM), "60°00.000'S, 0°30.000'W");
+        assert_eq!(Coordinate::new(0.0, 59.99999).format(CoordFormat::DMS), "60°00'00.0\"N, 0°00'00.0\"E");
+    }
+
+    #[test]
+    fn segments_intersect_equator_and_meridian() {
+        let crossing = segments_intersect(
+            Coordinate::from_lat_lon(0.0, -10.0),
+            Coordinate::from_lat_lon(0.0, 10.0),
+            Coordinate::from_lat_lon(-10.0, 0.0),
+            Coordinate::from_lat_lon(10.0, 0.0),
+        ).unwrap();
+        assert!(crossing.lat().abs() < 1e-9 && crossing.lon().abs() < 1e-9, "{:?}", crossing);
+
+        // The great circles still meet at (0, 0), but outside of the equator segment.
+        assert_eq!(segments_intersect(
+            Coordinate::from_lat_lon(0.0, 20.0),
+            Coordinate::from_lat_lon(0.0, 30.0),
+            Coordinate::from_lat_lon(-10.0, 0.0),
+            Coordinate::from_lat_lon(10.0, 0.0),
+        ), None);
+    }
+
+    #[test]
+    fn coordinate_in_polygon_around_the_north_pole() {
+        let ring: Vec<Coordinate> = (0..12)
+            .map(|i| Coordinate::from_lat_lon(80.0, -180.0 + 30.0*i as f64))
+            .collect();
+
+        assert!(coordinate_in_polygon(Coordinate::from_lat_lon(90.0, 0.0), &ring));
+        assert!(coordinate_in_polygon(Coordinate::from_lat_lon(85.0, 123.0), &ring));
+        assert!(!coordinate_in_polygon(Coordinate::from_lat_lon(0.0, 0.0), &ring));
+        assert!(!coordinate_in_polygon(Coordinate::from_lat_lon(75.0, 0.0), &ring));
+    }
+
+    #[test]
+    fn cardinal_direction_of_any_bearing() {
+        assert_eq!(cardinal_direction(0.0), "N");
+        assert_eq!(cardinal_direction(45.0), "NE");
+        assert_eq!(cardinal_direction(200.0), "SSW");
+        assert_eq!(cardinal_direction(350.0), "N");
+        assert_eq!(cardinal_direction(-10.0), "N");
+        assert_eq!(cardinal_direction(-90.0), "W");
+        assert_eq!(cardinal_direction(405.0), "NE");
+        assert_eq!(cardinal_direction(720.0), "N");
+    }
+
+    #[test]
+    fn snap_to_grid_rounds_to_cells() {
+        let coordinate = Coordinate::from_lat_lon(59.337, 18.072);
+        let snapped = coordinate.snap_to_grid(0.1);
+        assert!((snapped.lat() - 59.3).abs() < 1e-9, "{:?}", snapped);
+        assert!((snapped.lon() - 18.1).abs() < 1e-9, "{:?}", snapped);
+
+        assert_eq!(coordinate.snap_to_grid(0.0), coordinate);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+
+use crate::Point;
+
+/// Collects drawing operations as elements of an SVG document, for vector output that stays
+/// sharp at any size, for example when printing a map.
+///
+/// The operations mirror those of `SimpleImage`, with the same pixel coordinates, and take
+/// colors in the same order: `[u8; 4]` colors are BGRA, and text colors are RGB.
+/// Format the canvas, for example with `to_string`, to get the document.
+///
+/// Shapes with coordinates that are not finite, such as NaN, are skipped, since SVG viewers
+/// would reject the whole document.
+///
+/// Example
+/// ```
+/// let mut canvas = SvgCanvas::new(360, 180);
+/// canvas.draw_polygon(&project_all(&ring, 360.0, 180.0), [0x00, 0x80, 0x00, 0xFF]);
+/// std::fs::write("map.svg", canvas.to_string())?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct SvgCanvas {
+    width: u32,
+    height: u32,
+    elements: Vec<String>,
+}
+
+impl SvgCanvas {
+    /// Create an empty canvas with the given width and height.
+    pub fn new(width: u32, height: u32) -> Self {
+        SvgCanvas {
+            width,
+            height,
+            elements: vec![],
+        }
+    }
+
+    /// Getter for the width of the canvas
+    pub fn width(&self) -> u32 { self.width }
+    /// Getter for the height of the canvas
+    pub fn height(&self) -> u32 { self.height }
+
+    /// Draw a filled polygon with a black outline, like `SimpleImage::draw_polygon`.
+    pub fn draw_polygon(&mut self, polygon: &[Point], color: [u8; 4]) {
+        if !all_finite(polygon) {
+            return;
+        }
+        self.elements.push(format!(
+            r#"<polygon points="{}" {} stroke="rgb(0,0,0)"/>"#,
+            format_points(polygon), paint("fill", color),
+        ));
+    }
+
+    /// Draw a filled polygon without an outline, like `SimpleImage::fill_polygon`.
+    pub fn fill_polygon(&mut self, polygon: &[Point], color: [u8; 4]) {
+        if !all_finite(polygon) {
+            return;
+        }
+        self.elements.push(format!(
+            r#"<polygon points="{}" {}/>"#,
+            format_points(polygon), paint("fill", color),
+        ));
+    }
+
+    /// Draw a line between two points.
+    pub fn draw_line(&mut self, start: Point, end: Point, color: [u8; 4]) {
+        if !all_finite(&[start, end]) {
+            return;
+        }
+        self.elements.push(format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+            start.x, start.y, end.x, end.y, paint("stroke", color),
+        ));
+    }
+
+    /// Draw lines connecting all the points, in order.
+    pub fn draw_polyline(&mut self, points: &[Point], color: [u8; 4]) {
+        if !all_finite(points) {
+            return;
+        }
+        self.elements.push(format!(
+            r#"<polyline points="{}" fill="none" {}/>"#,
+            format_points(points), paint("stroke", color),
+        ));
+    }
+
+    /// Draw the stroke of a circle, which lies inside of the radius like the stroke drawn by
+    /// `SimpleWindow::stroke_circle`. The color is BGRA, as for `SimpleImage`.
+    pub fn stroke_circle(&mut self, cx: f64, cy: f64, radius: f64, thickness: f64, color: [u8; 4]) {
+        if ![cx, cy, radius, thickness].iter().all(|v| v.is_finite()) {
+            return;
+        }
+        self.elements.push(format!(
+            r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke-width="{}" {}/>"#,
+            cx, cy, radius - thickness/2.0, thickness, paint("stroke", color),
+        ));
+    }
+
+    /// Draw text in a font of the given family, such as "sans-serif", with the top left of the
+    /// text at (x, y). The font is chosen by whatever displays the document, so the size of
+    /// the text may differ slightly from `create_text_image`.
+    pub fn draw_text(&mut self, text: &str, x: f64, y: f64, family: &str, size: f32, color: [u8; 3]) {
+        if !x.is_finite() || !y.is_finite() || !size.is_finite() {
+            return;
+        }
+
+        let [r, g, b] = color;
+        self.elements.push(format!(
+            r#"<text x="{}" y="{}" font-family="{}" font-size="{}" dominant-baseline="hanging" fill="rgb({},{},{})">{}</text>"#,
+            x, y, escape(family), size, r, g, b, escape(text),
+        ));
+    }
+}
+
+impl fmt::Display for SvgCanvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.width, h = self.height,
+        )?;
+        for element in &self.elements {
+            writeln!(f, "  {}", element)?;
+        }
+        writeln!(f, "</svg>")
+    }
+}
+
+/// Returns true if both coordinates of every point are finite.
+fn all_finite(points: &[Point]) -> bool {
+    points.iter().all(|p| p.x.is_finite() && p.y.is_finite())
+}
+
+/// Format points as the value of a `points` attribute.
+fn format_points(points: &[Point]) -> String {
+    points.iter()
+        .map(|p| format!("{},{}", p.x, p.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Format a BGRA color as the attributes to fill or stroke with it.
+fn paint(attribute: &str, color: [u8; 4]) -> String {
+    let [b, g, r, a] = color;
+    format!(
+        r#"{attribute}="rgb({},{},{})" {attribute}-opacity="{}""#,
+        r, g, b, a as f64/255.0, attribute = attribute,
+    )
+}
+
+/// Escape the characters that have a special meaning in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_points_and_non_finite_shapes() {
+        let mut canvas = SvgCanvas::new(10, 10);
+        canvas.fill_polygon(&[Point::new(1.0, 2.0), Point::new(3.5, 4.0), Point::new(0.0, 9.0)], [0xFF, 0x00, 0x00, 0xFF]);
+        canvas.fill_polygon(&[Point::new(1.0, 2.0), Point::new(f64::NAN, 4.0), Point::new(0.0, 9.0)], [0xFF; 4]);
+        canvas.draw_line(Point::new(0.0, 0.0), Point::new(f64::INFINITY, 1.0), [0xFF; 4]);
+
+        let document = canvas.to_string();
+        assert!(document.contains(r#"<polygon points="1,2 3.5,4 0,9" fill="rgb(0,0,255)" fill-opacity="1"/>"#));
+        assert_eq!(document.lines().count(), 3);
+    }
+}
@@ -0,0 +1,271 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use crate::gui::{BlendMode, SimpleImage};
+use crate::{Coordinate, Point};
+
+/// The highest zoom level `TileLayer::render` accepts. Tile servers rarely go beyond 20,
+/// and at 30 the tiles are already about 4 centimeters wide.
+pub const MAX_ZOOM: u32 = 30;
+
+/// Find the position of a coordinate in the grid of map tiles at the given zoom level,
+/// as used by most web maps. Tiles use the web mercator projection, and at zoom level `z`
+/// there are `2^z` by `2^z` tiles, with tile (0, 0) in the top left corner.
+///
+/// The whole part of each returned component is the index of the tile containing the
+/// coordinate, and the fractional part is the position within that tile.
+pub fn coordinate_to_tile(coordinate: Coordinate, zoom: u32) -> Point {
+    use std::f64::consts::PI;
+
+    let n = 2f64.powi(zoom as i32);
+    let lat = coordinate.lat().clamp(-85.05112878, 85.05112878).to_radians();
+    let x = n*(coordinate.lon() + 180.0)/360.0;
+    let y = n*(1.0 - (lat.tan() + 1.0/lat.cos()).ln()/PI)/2.0;
+
+    Point::new(x, y)
+}
+
+/// Find the coordinate at a position in the grid of map tiles. The inverse of `coordinate_to_tile`.
+pub fn tile_to_coordinate(tile: Point, zoom: u32) -> Coordinate {
+    use std::f64::consts::PI;
+
+    let n = 2f64.powi(zoom as i32);
+    let lon = 360.0*tile.x/n - 180.0;
+    let lat = (PI*(1.0 - 2.0*tile.y/n)).sinh().atan().to_degrees();
+
+    Coordinate::new(lon, lat)
+}
+
+/// Something that can provide the images of map tiles, such as a cache on disk or a tile server.
+///
+/// The crate doesn't fetch anything on its own, so implement this for whatever source of tiles
+/// the application uses, and give it to a `TileLayer` to render maps from.
+pub trait TileSource {
+    /// Get the image of the tile at the given position in the grid of tiles at the given zoom level.
+    fn fetch(&self, x: u32, y: u32, zoom: u32) -> Result<SimpleImage, Box<dyn std::error::Error>>;
+}
+
+/// Renders views of a map by combining the tiles provided by a `TileSource`.
+pub struct TileLayer<S: TileSource> {
+    source: S,
+    tile_size: u32,
+}
+
+impl<S: TileSource> TileLayer<S> {
+    /// Create a layer of tiles from the given source, whose tiles are `tile_size` pixels wide
+    /// and high. Most tile servers use 256.
+    pub fn new(source: S, tile_size: u32) -> Self {
+        TileLayer {
+            source,
+            tile_size,
+        }
+    }
+
+    /// Getter for the source of tiles
+    pub fn source(&self) -> &S { &self.source }
+
+    /// Render an image of the map with the given width and height in pixels, centered on the
+    /// given coordinate, at the given zoom level. Only the tiles covering the image are fetched.
+    ///
+    /// The map repeats horizontally across the antimeridian. Areas beyond the top and bottom
+    /// of the map are left transparent.
+    ///
+    /// Fails for zoom levels above `MAX_ZOOM`, and if the source gives a tile that is not
+    /// `tile_size` pixels wide and high, since it would leave gaps or overlap its neighbors.
+    pub fn render(
+        &self,
+        center: Coordinate,
+        zoom: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        if zoom > MAX_ZOOM {
+            return Err(format!("zoom level {} is above the highest level of {}", zoom, MAX_ZOOM).into());
+        }
+
+        let tile_size = self.tile_size as f64;
+        let tiles = 1i64 << zoom;
+        let center = coordinate_to_tile(center, zoom);
+        let left = (center.x*tile_size - width as f64/2.0).floor() as i64;
+        let top = (center.y*tile_size - height as f64/2.0).floor() as i64;
+
+        let first_x = left.div_euclid(self.tile_size as i64);
+        let last_x = (left + width as i64 - 1).div_euclid(self.tile_size as i64);
+        let first_y = top.div_euclid(self.tile_size as i64).max(0);
+        let last_y = (top + height as i64 - 1).div_euclid(self.tile_size as i64).min(tiles - 1);
+
+        let mut image = SimpleImage::new(width, height);
+        for tile_y in first_y..=last_y {
+            for tile_x in first_x..=last_x {
+                let tile = self.source.fetch(tile_x.rem_euclid(tiles) as u32, tile_y as u32, zoom)?;
+                if tile.width() != self.tile_size || tile.height() != self.tile_size {
+                    return Err(format!(
+                        "tile ({}, {}) at zoom level {} is {}x{} pixels rather than {}x{}",
+                        tile_x.rem_euclid(tiles), tile_y, zoom,
+                        tile.width(), tile.height(), self.tile_size, self.tile_size,
+                    ).into());
+                }
+                let x = tile_x*self.tile_size as i64 - left;
+                let y = tile_y*self.tile_size as i64 - top;
+                tile.blend_onto(&mut image, x as i32, y as i32, BlendMode::None);
+            }
+        }
+
+        Ok(image)
+    }
+}
+
+/// Wraps another `TileSource`, remembering the most recently used tiles so they don't have
+/// to be fetched again, for example while panning back and forth over the same area.
+///
+/// When more than `capacity` tiles are remembered, the least recently used one is forgotten.
+/// Failed fetches are not remembered.
+pub struct TileCache<S: TileSource> {
+    source: S,
+    capacity: usize,
+    tiles: RefCell<HashMap<(u32, u32, u32), CachedTile>>,
+    clock: Cell<u64>,
+}
+
+struct CachedTile {
+    image: SimpleImage,
+    last_used: u64,
+}
+
+impl<S: TileSource> TileCache<S> {
+    /// Create a cache in front of the given source, remembering at most `capacity` tiles.
+    pub fn new(source: S, capacity: usize) -> Self {
+        TileCache {
+            source,
+            capacity,
+            tiles: RefCell::new(HashMap::new()),
+            clock: Cell::new(0),
+        }
+    }
+
+    /// Getter for the wrapped source of tiles
+    pub fn source(&self) -> &S { &self.source }
+
+    /// The number of tiles currently remembered
+    pub fn len(&self) -> usize { self.tiles.borrow().len() }
+
+    /// Returns true if no tiles are currently remembered
+    pub fn is_empty(&self) -> bool { self.tiles.borrow().is_empty() }
+
+    /// Forget all remembered tiles
+    pub fn clear(&self) {
+        self.tiles.borrow_mut().clear();
+    }
+}
+
+impl<S: TileSource> TileSource for TileCache<S> {
+    fn fetch(&self, x: u32, y: u32, zoom: u32) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+
+        let key = (zoom, x, y);
+        if let Some(cached) = self.tiles.borrow_mut().get_mut(&key) {
+            cached.last_used = now;
+            return Ok(cached.image.clone());
+        }
+
+        let tile = self.source.fetch(x, y, zoom)?;
+        if self.capacity == 0 {
+            return Ok(tile);
+        }
+
+        let mut tiles = self.tiles.borrow_mut();
+        if tiles.len() >= self.capacity {
+            let oldest = tiles.iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                tiles.remove(&oldest);
+            }
+        }
+        tiles.insert(key, CachedTile {
+            image: tile.clone(),
+            last_used: now,
+        });
+
+        Ok(tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gives 1x1 tiles, remembering which tiles were fetched.
+    struct CountingSource {
+        fetched: RefCell<Vec<(u32, u32, u32)>>,
+    }
+
+    impl TileSource for CountingSource {
+        fn fetch(&self, x: u32, y: u32, zoom: u32) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+            self.fetched.borrow_mut().push((x, y, zoom));
+            Ok(SimpleImage::new(1, 1))
+        }
+    }
+
+    #[test]
+    fn cache_fetches_once_and_evicts_least_recently_used() {
+        let cache = TileCache::new(CountingSource { fetched: RefCell::new(vec![]) }, 2);
+
+        cache.fetch(0, 0, 1).unwrap();
+        cache.fetch(1, 0, 1).unwrap();
+        cache.fetch(0, 0, 1).unwrap();
+        assert_eq!(*cache.source().fetched.borrow(), [(0, 0, 1), (1, 0, 1)]);
+
+        // (1, 0) is now the least recently used, so it makes room for (0, 1).
+        cache.fetch(0, 1, 1).unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.fetch(0, 0, 1).unwrap();
+        cache.fetch(0, 1, 1).unwrap();
+        assert_eq!(cache.source().fetched.borrow().len(), 3);
+
+        cache.fetch(1, 0, 1).unwrap();
+        assert_eq!(cache.source().fetched.borrow().len(), 4);
+    }
+
+    /// Gives tiles of a single color that depends on which tile it is.
+    struct SolidSource {
+        size: u32,
+    }
+
+    impl SolidSource {
+        fn color(x: u32, y: u32) -> [u8; 4] {
+            [0x40 + x as u8, 0x80 + y as u8, 0xC0, 0xFF]
+        }
+    }
+
+    impl TileSource for SolidSource {
+        fn fetch(&self, x: u32, y: u32, _zoom: u32) -> Result<SimpleImage, Box<dyn std::error::Error>> {
+            let data = SolidSource::color(x, y).repeat((self.size*self.size) as usize);
+            SimpleImage::from_bgra(self.size, self.size, data)
+        }
+    }
+
+    #[test]
+    fn render_joins_tiles_and_leaves_outside_the_map_transparent() {
+        // At zoom 1 the map is 2x2 tiles of 4x4 pixels, so (0, 0) is at the center of the map, and a
+        // 8x12 image covers all tiles with 2 transparent rows above and below.
+        let layer = TileLayer::new(SolidSource { size: 4 }, 4);
+        let image = layer.render(Coordinate::new(0.0, 0.0), 1, 8, 12).unwrap();
+
+        assert_eq!(image[(3, 1)], [0; 4]);
+        assert_eq!(image[(3, 2)], SolidSource::color(0, 0));
+        assert_eq!(image[(4, 2)], SolidSource::color(1, 0));
+        assert_eq!(image[(3, 5)], SolidSource::color(0, 0));
+        assert_eq!(image[(3, 6)], SolidSource::color(0, 1));
+        assert_eq!(image[(4, 6)], SolidSource::color(1, 1));
+        assert_eq!(image[(7, 9)], SolidSource::color(1, 1));
+        assert_eq!(image[(7, 10)], [0; 4]);
+    }
+
+    #[test]
+    fn render_rejects_tiles_of_the_wrong_size() {
+        let layer = TileLayer::new(SolidSource { size: 3 }, 4);
+        assert!(layer.render(Coordinate::new(0.0, 0.0), 1, 8, 8).is_err());
+    }
+}
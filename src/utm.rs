@@ -0,0 +1,321 @@
+use crate::Coordinate;
+
+/// Semi-major axis of the WGS 84 ellipsoid, in meters.
+const WGS84_A: f64 = 6378137.0;
+/// Flattening of the WGS 84 ellipsoid.
+const WGS84_F: f64 = 1.0/298.257223563;
+/// Scale factor along the central meridian of every UTM zone.
+const UTM_K0: f64 = 0.9996;
+
+/// Letters of the latitude bands, 8 degrees each from 80 south, except X which covers 72 to 84 north.
+const LATITUDE_BANDS: &[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+/// Letters of the 100km square columns of MGRS, in sets that repeat every three zones.
+const COLUMN_LETTERS: [&[u8]; 3] = [b"ABCDEFGH", b"JKLMNPQR", b"STUVWXYZ"];
+/// Letters of the 100km square rows of MGRS, repeating every 2000km of northing.
+const ROW_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+
+/// A position in the Universal Transverse Mercator system, which divides the earth into 60
+/// zones, each 6 degrees of longitude wide, and gives positions within a zone in meters.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Utm {
+    /// Zone number, from 1 to 60.
+    pub zone: u8,
+    /// Latitude band letter, from C in the south to X in the north. Bands N and up are
+    /// in the northern hemisphere, which decides where northing is measured from.
+    pub band: char,
+    /// Meters east of a line 500km west of the central meridian of the zone.
+    pub easting: f64,
+    /// Meters north of the equator, or in the southern hemisphere,
+    /// of a line 10000km south of the equator.
+    pub northing: f64,
+}
+
+impl Utm {
+    /// Check whether this position is in the northern hemisphere, judging by its band.
+    pub fn is_north(&self) -> bool {
+        self.band >= 'N'
+    }
+
+    /// Convert the position back to a coordinate. The inverse of `Coordinate::to_utm`.
+    pub fn to_coordinate(&self) -> Coordinate {
+        let e2 = WGS84_F*(2.0 - WGS84_F);
+        let ep2 = e2/(1.0 - e2);
+        let lon0 = central_meridian(self.zone).to_radians();
+
+        let false_northing = if self.is_north() { 0.0 } else { 10_000_000.0 };
+        let m = (self.northing - false_northing)/UTM_K0;
+        let mu = m/(WGS84_A*(1.0 - e2/4.0 - 3.0*e2.powi(2)/64.0 - 5.0*e2.powi(3)/256.0));
+
+        // Footpoint latitude, the latitude on the central meridian with the same northing.
+        let e1 = (1.0 - (1.0 - e2).sqrt())/(1.0 + (1.0 - e2).sqrt());
+        let phi1 = mu
+            + (3.0*e1/2.0 - 27.0*e1.powi(3)/32.0)*(2.0*mu).sin()
+            + (21.0*e1.powi(2)/16.0 - 55.0*e1.powi(4)/32.0)*(4.0*mu).sin()
+            + (151.0*e1.powi(3)/96.0)*(6.0*mu).sin()
+            + (1097.0*e1.powi(4)/512.0)*(8.0*mu).sin();
+
+        let c1 = ep2*phi1.cos().powi(2);
+        let t1 = phi1.tan().powi(2);
+        let n1 = WGS84_A/(1.0 - e2*phi1.sin().powi(2)).sqrt();
+        let r1 = WGS84_A*(1.0 - e2)/(1.0 - e2*phi1.sin().powi(2)).powf(1.5);
+        let d = (self.easting - 500_000.0)/(n1*UTM_K0);
+
+        let lat = phi1 - (n1*phi1.tan()/r1)*(
+            d.powi(2)/2.0
+            - (5.0 + 3.0*t1 + 10.0*c1 - 4.0*c1.powi(2) - 9.0*ep2)*d.powi(4)/24.0
+            + (61.0 + 90.0*t1 + 298.0*c1 + 45.0*t1.powi(2) - 252.0*ep2 - 3.0*c1.powi(2))*d.powi(6)/720.0
+        );
+        let lon = lon0 + (
+            d
+            - (1.0 + 2.0*t1 + c1)*d.powi(3)/6.0
+            + (5.0 - 2.0*c1 + 28.0*t1 - 3.0*c1.powi(2) + 8.0*ep2 + 24.0*t1.powi(2))*d.powi(5)/120.0
+        )/phi1.cos();
+
+        Coordinate::new(lon.to_degrees(), lat.to_degrees())
+    }
+}
+
+impl Coordinate {
+    /// Convert the coordinate to a UTM position on the WGS 84 ellipsoid, in the zone containing it,
+    /// including the exceptions to the zones around Norway and Svalbard.
+    ///
+    /// UTM is only defined from 80 degrees south to 84 degrees north.
+    /// Beyond that, the position is given in the southernmost or northernmost band.
+    pub fn to_utm(&self) -> Utm {
+        let zone = utm_zone(self.lon(), self.lat());
+        self.utm_in_zone(zone)
+    }
+
+    /// Convert the coordinate to a UTM position in the given zone, even if it lies outside of it.
+    fn utm_in_zone(&self, zone: u8) -> Utm {
+        let e2 = WGS84_F*(2.0 - WGS84_F);
+        let ep2 = e2/(1.0 - e2);
+        let phi = self.lat().to_radians();
+        let lon0 = central_meridian(zone);
+        let dlon = ((self.lon() - lon0 + 180.0).rem_euclid(360.0) - 180.0).to_radians();
+
+        let n = WGS84_A/(1.0 - e2*phi.sin().powi(2)).sqrt();
+        let t = phi.tan().powi(2);
+        let c = ep2*phi.cos().powi(2);
+        let a = phi.cos()*dlon;
+
+        // Distance along the central meridian from the equator.
+        let m = WGS84_A*(
+            (1.0 - e2/4.0 - 3.0*e2.powi(2)/64.0 - 5.0*e2.powi(3)/256.0)*phi
+            - (3.0*e2/8.0 + 3.0*e2.powi(2)/32.0 + 45.0*e2.powi(3)/1024.0)*(2.0*phi).sin()
+            + (15.0*e2.powi(2)/256.0 + 45.0*e2.powi(3)/1024.0)*(4.0*phi).sin()
+            - (35.0*e2.powi(3)/3072.0)*(6.0*phi).sin()
+        );
+
+        let easting = 500_000.0 + UTM_K0*n*(
+            a
+            + (1.0 - t + c)*a.powi(3)/6.0
+            + (5.0 - 18.0*t + t.powi(2) + 72.0*c - 58.0*ep2)*a.powi(5)/120.0
+        );
+        let mut northing = UTM_K0*(m + n*phi.tan()*(
+            a.powi(2)/2.0
+            + (5.0 - t + 9.0*c + 4.0*c.powi(2))*a.powi(4)/24.0
+            + (61.0 - 58.0*t + t.powi(2) + 600.0*c - 330.0*ep2)*a.powi(6)/720.0
+        ));
+        if self.lat() < 0.0 {
+            northing += 10_000_000.0;
+        }
+
+        Utm {
+            zone,
+            band: latitude_band(self.lat()),
+            easting,
+            northing,
+        }
+    }
+
+    /// Format the coordinate as a Military Grid Reference System reference, like "33UXP0450080500".
+    ///
+    /// The reference is the UTM zone and band, two letters for the 100km square, and `precision`
+    /// digits each of easting and northing within the square, so a precision of 5 points out a
+    /// square meter and a precision of 0 only the 100km square. Precision is at most 5.
+    /// The digits are truncated, so the reference names the square the coordinate is in.
+    ///
+    /// Like `to_utm`, the polar regions beyond 80 degrees south and 84 degrees north are not
+    /// supported, and are given in the nearest band.
+    pub fn to_mgrs(&self, precision: usize) -> String {
+        let precision = precision.min(5);
+        let utm = self.to_utm();
+        let (column, row) = square_letters(utm.zone, utm.easting, utm.northing);
+
+        let scale = 10f64.powi(5 - precision as i32);
+        let easting = (utm.easting.rem_euclid(100_000.0)/scale).floor() as u32;
+        let northing = (utm.northing.rem_euclid(100_000.0)/scale).floor() as u32;
+
+        let mut reference = format!("{}{}{}{}", utm.zone, utm.band, column, row);
+        if precision > 0 {
+            reference += &format!("{:0width$}{:0width$}", easting, northing, width = precision);
+        }
+        reference
+    }
+
+    /// Parse a Military Grid Reference System reference, like "33UXP0450080500", as made by `to_mgrs`.
+    /// Spaces in the reference are ignored, and letters may be in either case.
+    ///
+    /// Returns the south west corner of the square the reference names.
+    pub fn from_mgrs(reference: &str) -> Result<Coordinate, Box<dyn std::error::Error>> {
+        let reference: String = reference.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let invalid = || format!("invalid MGRS reference \"{}\"", reference);
+
+        let digits = reference.chars().take_while(|c| c.is_ascii_digit()).count();
+        let zone: u8 = reference[..digits].parse().map_err(|_| invalid())?;
+        if !(1..=60).contains(&zone) {
+            return Err(invalid().into());
+        }
+
+        let rest = &reference.as_bytes()[digits..];
+        if rest.len() < 3 || (rest.len() - 3) & 1 != 0 || rest.len() > 13 {
+            return Err(invalid().into());
+        }
+        let band = LATITUDE_BANDS.iter().position(|&b| b == rest[0]).ok_or_else(invalid)?;
+        let column = COLUMN_LETTERS[(zone as usize - 1) % 3].iter()
+            .position(|&b| b == rest[1])
+            .ok_or_else(invalid)?;
+        let row = ROW_LETTERS.iter().position(|&b| b == rest[2]).ok_or_else(invalid)?;
+
+        let numbers = std::str::from_utf8(&rest[3..])?;
+        if !numbers.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid().into());
+        }
+        let precision = numbers.len()/2;
+        let scale = 10f64.powi(5 - precision as i32);
+        let parse = |s: &str| if s.is_empty() { Ok(0.0) } else { s.parse::<f64>().map(|n| n*scale) };
+        let easting = parse(&numbers[..precision])?;
+        let northing = parse(&numbers[precision..])?;
+
+        // The row letters repeat every 2000km, so find the repetition within the latitude band.
+        let row_offset = if zone & 1 == 0 { 5 } else { 0 };
+        let row = (row + ROW_LETTERS.len() - row_offset) % ROW_LETTERS.len();
+        let mut northing = row as f64*100_000.0 + northing;
+        let band_south = -80.0 + 8.0*band as f64;
+        let min_northing = [0.0, 3.0, -3.0].iter()
+            .map(|dlon| Coordinate::new(central_meridian(zone) + dlon, band_south)
+                .utm_in_zone(zone)
+                .northing)
+            .fold(f64::MAX, f64::min);
+        // Allow for squares that start just south of the band.
+        while northing + 100_000.0 < min_northing {
+            northing += 2_000_000.0;
+        }
+
+        let utm = Utm {
+            zone,
+            band: LATITUDE_BANDS[band] as char,
+            easting: (column + 1) as f64*100_000.0 + easting,
+            northing,
+        };
+        Ok(utm.to_coordinate())
+    }
+}
+
+/// Get the letters of the 100km MGRS square containing a UTM position.
+fn square_letters(zone: u8, easting: f64, northing: f64) -> (char, char) {
+    let columns = COLUMN_LETTERS[(zone as usize - 1) % 3];
+    let column = ((easting/100_000.0).floor() as usize).clamp(1, columns.len()) - 1;
+
+    let row_offset = if zone & 1 == 0 { 5 } else { 0 };
+    let row = ((northing/100_000.0).floor() as usize + row_offset) % ROW_LETTERS.len();
+
+    (columns[column] as char, ROW_LETTERS[row] as char)
+}
+
+/// Get the UTM zone containing a coordinate.
+fn utm_zone(lon: f64, lat: f64) -> u8 {
+    let lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+
+    // South west Norway is in a wider zone 32.
+    if (56.0..64.0).contains(&lat) && (3.0..12.0).contains(&lon) {
+        return 32;
+    }
+
+    // Svalbard only uses the odd zones 31 to 37.
+    if (72.0..=84.0).contains(&lat) && (0.0..42.0).contains(&lon) {
+        return match lon {
+            lon if lon < 9.0 => 31,
+            lon if lon < 21.0 => 33,
+            lon if lon < 33.0 => 35,
+            _ => 37,
+        };
+    }
+
+    (((lon + 180.0)/6.0).floor() as u8).min(59) + 1
+}
+
+/// Get the longitude in degrees of the central meridian of a UTM zone.
+fn central_meridian(zone: u8) -> f64 {
+    6.0*zone as f64 - 183.0
+}
+
+/// Get the letter of the latitude band containing a latitude.
+fn latitude_band(lat: f64) -> char {
+    let index = ((lat + 80.0)/8.0).floor().clamp(0.0, 19.0) as usize;
+    LATITUDE_BANDS[index] as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mgrs_round_trips_published_reference() {
+        // The Washington Monument, as given by the NGA.
+        // The published position, with four decimals, lies about 9 meters west of this square.
+        let monument = Coordinate::from_mgrs("18SUJ2348706483").unwrap();
+        let published = Coordinate::from_lat_lon(38.8895, -77.0353);
+        assert!(monument.great_circle_distance(published) < 10.0, "decoded to {:?}", monument);
+        // The same square worked out with Karney's series for the transverse mercator projection.
+        assert!((monument.lat() - 38.8894996).abs() < 1e-7 && (monument.lon() + 77.0351970).abs() < 1e-7);
+
+        // The reference names the south west corner of a square meter, so encode its middle.
+        let mut utm = monument.to_utm();
+        utm.easting += 0.5;
+        utm.northing += 0.5;
+        assert_eq!(utm.to_coordinate().to_mgrs(5), "18SUJ2348706483");
+        assert_eq!(Coordinate::from_mgrs("18s uj 23487 06483").unwrap(), monument);
+    }
+
+    #[test]
+    fn utm_zone_exceptions() {
+        // Bergen is west of 6 degrees east, but in the widened zone 32.
+        let bergen = Coordinate::from_lat_lon(60.39, 5.32).to_utm();
+        assert_eq!((bergen.zone, bergen.band), (32, 'V'));
+        assert_eq!(Coordinate::from_lat_lon(60.39, 2.0).to_utm().zone, 31);
+
+        // Svalbard skips the even zones 32, 34 and 36.
+        let zones: Vec<(u8, char)> = [5.0, 10.0, 15.0, 25.0, 35.0].iter()
+            .map(|&lon| Coordinate::from_lat_lon(78.0, lon).to_utm())
+            .map(|utm| (utm.zone, utm.band))
+            .collect();
+        assert_eq!(zones, [(31, 'X'), (33, 'X'), (33, 'X'), (35, 'X'), (37, 'X')]);
+        assert!(Coordinate::from_lat_lon(78.22, 15.65).to_mgrs(5).starts_with("33X"));
+    }
+
+    #[test]
+    fn from_mgrs_rejects_invalid_references() {
+        let invalid = [
+            "",
+            "18",
+            "SUJ2348706483",
+            "0SUJ2348706483",
+            "61SUJ2348706483",
+            "18IUJ2348706483",
+            "18SIJ2348706483",
+            "18SUW2348706483",
+            "18SU",
+            "18SUJ234870648",
+            "18SUJ23487O6483",
+            "18SUJ234870648312",
+        ];
+        for reference in invalid {
+            assert!(Coordinate::from_mgrs(reference).is_err(), "accepted {:?}", reference);
+        }
+    }
+}